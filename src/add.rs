@@ -1,9 +1,306 @@
-use anyhow::{Result, Error};
+use std::path::{Path, PathBuf};
 
-use crate::AddArgs;
+use anyhow::{Result, Error, Context};
+use log::{info, debug, warn};
 
+use crate::{members, sync, AddArgs, AddClassArgs, AddCommands, AddModuleArgs, api::ExtensionApi, codegen, level::{self, InitLevel}, naming, print_output, manifest::{Manifest, Module, Class}, regions, templates::Templates, version::GodotVersion, workspace::Workspace};
 
 
-pub fn add_extension(_nameargs: AddArgs) -> Result<(), Error> {
+
+pub fn add(ws: &mut Workspace, addargs: AddArgs, godot_dir: &str, src_dir: &str) -> Result<(), Error> {
+    let path = match addargs.path {
+        Some(path) => path,
+        None => std::env::current_dir().with_context(|| "Getting current directory.")?,
+    };
+
+    ws.set_root(&path);
+    // Prefer the folders recorded in the manifest over the defaults
+    let mut manifest = Manifest::load(ws, &path)?;
+    let (godot_dir, src_dir) = match &manifest {
+        Some(manifest) => (manifest.project.godot_dir.clone(), manifest.project.src_dir.clone()),
+        None => (godot_dir.to_string(), src_dir.to_string()),
+    };
+
+    match addargs.command {
+        Some(AddCommands::Module(moduleargs)) => add_extension(ws, &path, &moduleargs, &addargs.base, &godot_dir, &src_dir, manifest.as_mut())?,
+        Some(AddCommands::Class(classargs)) => add_class(ws, &path, &classargs, &addargs.base, &src_dir, manifest.as_mut())?,
+        Some(AddCommands::Method(methodargs)) => members::add_method(ws, &path, &methodargs, &src_dir, manifest.as_ref())?,
+        Some(AddCommands::Property(propertyargs)) => members::add_property(ws, &path, &propertyargs, &src_dir, manifest.as_ref())?,
+        Some(AddCommands::Signal(signalargs)) => members::add_signal(ws, &path, &signalargs, &src_dir, manifest.as_ref())?,
+        Some(AddCommands::Enum(enumargs)) => members::add_enum(ws, &path, &enumargs, &src_dir, manifest.as_ref())?,
+        Some(AddCommands::Constant(constantargs)) => members::add_constant(ws, &path, &constantargs, &src_dir, manifest.as_ref())?,
+        None => {
+            let name = addargs.name.with_context(|| "Missing the name of the module that should be added.")?;
+            add_extension(ws, &path, &AddModuleArgs { name, class: None }, &addargs.base, &godot_dir, &src_dir, manifest.as_mut())?
+        },
+    };
+
+    if let Some(manifest) = manifest {
+        manifest.save(ws, &path)?;
+    };
+
+    if !addargs.nobuild {
+        build(ws, &path);
+    };
+
+    Ok(())
+}
+
+
+pub fn add_extension(ws: &mut Workspace, path: &Path, moduleargs: &AddModuleArgs, base: &str, godot_dir: &str, src_dir: &str, manifest: Option<&mut Manifest>) -> Result<(), Error> {
+    let pathstr = path.to_str().unwrap();
+    let name = moduleargs.name.as_str();
+    // The first class of the module is named after the module unless given
+    let classname = moduleargs.class.as_deref().unwrap_or(name);
+    naming::validate_module_name(name)?;
+    naming::validate_class_name(classname)?;
+    // Projects without a manifest get the templates of the default version
+    let version = manifest.as_ref().map(|m| m.project.godot_version).unwrap_or_default();
+    let templates = Templates::load(ws, path)?;
+
+    let godot_folder = path.join(godot_dir);
+    if !ws.is_dir(&godot_folder) {
+        return Err(Error::msg(format!("Could not find the '{}' folder inside '{}'. Did you run 'gdsetup init' there?", godot_dir, pathstr)));
+    };
+
+    // Refuse to touch anything if a module with that name already exists
+    if manifest.as_ref().is_some_and(|m| m.find_module(name).is_some()) {
+        return Err(Error::msg(format!("A module named '{}' already exists in the manifest.", name)));
+    };
+    let class_name_first_upper = naming::to_pascal_case(classname);
+    let api = ExtensionApi::load(ws, path, manifest.as_deref())?;
+    api.check_class_name(&class_name_first_upper)?;
+    api.check_base_class(base)?;
+    if manifest.as_ref().is_some_and(|m| m.modules.iter().flat_map(|m| m.classes.iter()).any(|c| c.name == class_name_first_upper)) {
+        return Err(Error::msg(format!("A class named '{}' already exists in the manifest.", class_name_first_upper)));
+    };
+    let gdextension_path = godot_folder.join(format!("{}.gdextension", name));
+    if ws.exists(&gdextension_path) {
+        return Err(Error::msg(format!("A module named '{}' already exists ('{}').", name, gdextension_path.display())));
+    };
+    let module_folder = path.join(src_dir).join(name);
+    if ws.exists(&module_folder) {
+        return Err(Error::msg(format!("A module named '{}' already exists ('{}').", name, module_folder.display())));
+    };
+    let extension_list_path = godot_folder.join(".godot").join("extension_list.cfg");
+    let extension_list = ws.read_to_string(&extension_list_path).unwrap_or_default();
+    let extension_entry = format!("res://{}.gdextension", name);
+    if extension_list.lines().any(|line| line.trim() == extension_entry) {
+        return Err(Error::msg(format!("'{}' is already listed in '{}'.", extension_entry, extension_list_path.display())));
+    };
+
+    // Create the godot-relevant files for the extension
+    info!("Creating '{}'", gdextension_path.display());
+    ws.write(&gdextension_path, codegen::generate_gdextension(&templates, name, version)?)
+        .with_context(|| format!("Tried creating the '{}.gdextension' file.", name))?;
+    ws.create_dir_all(&godot_folder.join(".godot"))
+        .with_context(|| "Tried creating a .godot folder")?;
+    ws.write(&extension_list_path, codegen::add_to_gdextension_list(&extension_list, name))
+        .with_context(|| format!("Tried adding '{}' to '{}'", extension_entry, extension_list_path.display()))?;
+
+    // Every additional module lives in its own subfolder of 'src/'
+    info!("Creating module folder '{}'", module_folder.display());
+    ws.create_dir_all(&module_folder).with_context(|| format!("Failed to create directory '{}'", module_folder.display()))?;
+    let level = InitLevel::for_base(base, &api);
+    ws.write(&module_folder.join("register_types.cpp"), codegen::generate_register_cpp(&templates, name, classname, base, level, &api, version)?)?;
+    ws.write(&module_folder.join("register_types.h"), codegen::generate_register_h(&templates, name, version)?)?;
+    ws.write(&module_folder.join(format!("{}.cpp", classname)), codegen::generate_class_cpp(&templates, classname, base, &[], &api, version)?)?;
+    ws.write(&module_folder.join(format!("{}.h", classname)), codegen::generate_class_h(&templates, classname, base, &[], &api, version)?)?;
+
+    // Add a build target for the new module
+    let sconstruct_path = path.join("SConstruct");
+    if ws.exists(&sconstruct_path) {
+        info!("Adding a build target for '{}' to SConstruct", name);
+        let mut sconstruct = ws.read_to_string(&sconstruct_path).with_context(|| "Tried reading SConstruct")?;
+        // Older projects end with an indented empty line
+        sconstruct.truncate(sconstruct.trim_end().len());
+        sconstruct.push('\n');
+        sconstruct.push_str(&codegen::generate_sconstruct_module(&templates, name, godot_dir, src_dir, version)?);
+        ws.write(&sconstruct_path, sconstruct).with_context(|| "Tried writing SConstruct")?;
+    };
+    let cmake_path = path.join("CMakeLists.txt");
+    if ws.exists(&cmake_path) {
+        info!("Adding a build target for '{}' to CMakeLists.txt", name);
+        let mut cmakelists = ws.read_to_string(&cmake_path).with_context(|| "Tried reading CMakeLists.txt")?;
+        // Older projects end with an indented empty line
+        cmakelists.truncate(cmakelists.trim_end().len());
+        cmakelists.push('\n');
+        cmakelists.push_str(&codegen::generate_cmakelists_module(&templates, name, src_dir, version)?);
+        ws.write(&cmake_path, cmakelists).with_context(|| "Tried writing CMakeLists.txt")?;
+    };
+
+    if let Some(manifest) = manifest {
+        let mut module = Module::new(name, &format!("{}/{}", src_dir, name));
+        module.classes.push(Class {
+            name: class_name_first_upper,
+            file: classname.to_string(),
+            base: base.to_string(),
+            level: None,
+        });
+        manifest.modules.push(module);
+    };
+
     Ok(())
-}
\ No newline at end of file
+}
+
+
+pub fn add_class(ws: &mut Workspace, path: &Path, classargs: &AddClassArgs, base: &str, src_dir: &str, manifest: Option<&mut Manifest>) -> Result<(), Error> {
+    let classname = classargs.name.as_str();
+    let module = classargs.module.as_deref();
+    naming::validate_class_name(classname)?;
+    let class_name_first_upper = naming::to_pascal_case(classname);
+    let api = ExtensionApi::load(ws, path, manifest.as_deref())?;
+    api.check_class_name(&class_name_first_upper)?;
+    api.check_base_class(base)?;
+    // Resolve the overrides before anything is written, a typo should not leave half a class behind
+    let overrides = classargs.overrides.iter()
+        .map(|method| api.find_virtual(base, method.trim()))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let tagged_level = classargs.level.as_deref().map(|level| level.parse::<InitLevel>()).transpose()?;
+    let level = tagged_level.unwrap_or_else(|| InitLevel::for_base(base, &api));
+    if tagged_level.is_some_and(|tagged| tagged != InitLevel::Editor) && api.is_editor_class(base) {
+        warn!("'{}' only exists inside the editor, registering '{}' at the {} level will fail in exported projects.", base, class_name_first_upper, level);
+    };
+    if let Some(manifest) = &manifest {
+        if manifest.modules.iter().flat_map(|m| m.classes.iter()).any(|c| c.name == class_name_first_upper) {
+            return Err(Error::msg(format!("A class named '{}' already exists in the manifest.", class_name_first_upper)));
+        };
+    };
+
+    let module_folder = get_module_folder(ws, path, module, src_dir, manifest.as_deref())?;
+    let register_cpp_path = module_folder.join("register_types.cpp");
+    if !ws.exists(&register_cpp_path) {
+        return Err(Error::msg(format!("Could not find '{}'. Does the module exist?", register_cpp_path.display())));
+    };
+
+    // Refuse to overwrite existing classes
+    let class_cpp_path = module_folder.join(format!("{}.cpp", classname));
+    let class_h_path = module_folder.join(format!("{}.h", classname));
+    for file in [&class_cpp_path, &class_h_path] {
+        if ws.exists(file) {
+            return Err(Error::msg(format!("'{}' already exists.", file.display())));
+        };
+    };
+
+    info!("Creating class '{}' in '{}'", class_name_first_upper, module_folder.display());
+    let version = manifest.as_ref().map(|m| m.project.godot_version).unwrap_or_default();
+    let templates = Templates::load(ws, path)?;
+    ws.write(&class_cpp_path, codegen::generate_class_cpp(&templates, classname, base, &overrides, &api, version)?)
+        .with_context(|| format!("Tried creating '{}'", class_cpp_path.display()))?;
+    ws.write(&class_h_path, codegen::generate_class_h(&templates, classname, base, &overrides, &api, version)?)
+        .with_context(|| format!("Tried creating '{}'", class_h_path.display()))?;
+
+    let register_cpp = ws.read_to_string(&register_cpp_path)
+        .with_context(|| format!("Tried reading '{}'", register_cpp_path.display()))?;
+    let register_cpp = register_class(&register_cpp, classname, base, level, version)
+        .with_context(|| format!("Tried registering '{}' in '{}'", classname, register_cpp_path.display()))?;
+    ws.write(&register_cpp_path, register_cpp)
+        .with_context(|| format!("Tried writing '{}'", register_cpp_path.display()))?;
+
+    if let Some(manifest) = manifest {
+        let module = match module {
+            Some(module) => manifest.find_module_mut(module),
+            None => manifest.modules.first_mut(),
+        };
+        if let Some(module) = module {
+            module.classes.push(Class {
+                name: class_name_first_upper,
+                file: classname.to_string(),
+                base: base.to_string(),
+                level: tagged_level,
+            });
+        };
+    };
+
+    Ok(())
+}
+
+
+/// Returns the source folder of a module. The module created by 'gdsetup init' lives directly inside 'src/'
+fn get_module_folder(ws: &Workspace, path: &Path, module: Option<&str>, src_dir: &str, manifest: Option<&Manifest>) -> Result<PathBuf, Error> {
+    if let Some(manifest) = manifest {
+        let found = match module {
+            Some(module) => manifest.find_module(module),
+            None => manifest.modules.first(),
+        };
+        return match found {
+            Some(found) => Ok(path.join(&found.src_dir)),
+            None => Err(Error::msg(format!("Could not find a module named '{}' in the manifest.", module.unwrap_or_default()))),
+        };
+    };
+
+    let src_folder = path.join(src_dir);
+    let Some(module) = module else {
+        return Ok(src_folder);
+    };
+    if ws.is_dir(&src_folder.join(module)) {
+        return Ok(src_folder.join(module));
+    };
+    let register_cpp = ws.read_to_string(&src_folder.join("register_types.cpp")).unwrap_or_default();
+    if register_cpp.contains(&format!("initialize_{}_module", module)) {
+        return Ok(src_folder);
+    };
+    Err(Error::msg(format!("Could not find a module named '{}' inside '{}'.", module, src_folder.display())))
+}
+
+
+/// Adds the '#include' and the 'ClassDB::register_class' call for a class to the contents of a register_types.cpp.
+/// They are appended to the 'includes' region and the block of 'level' inside the 'registrations' region, projects
+/// without the regions are edited line by line. Those only register at the scene level and get the regions otherwise
+fn register_class(register_cpp: &str, classname: &str, base: &str, level: InitLevel, version: GodotVersion) -> Result<String, Error> {
+    let include = format!("#include \"{}.h\"", classname);
+    let class_name = naming::to_pascal_case(classname);
+    let registration = format!("ClassDB::register_class<{}>();", class_name);
+    let plugin = level::get_plugin_registration(&class_name, base, version);
+
+    let has_regions = regions::find_region(register_cpp, "includes").is_some() && regions::find_region(register_cpp, "registrations").is_some();
+    if has_regions || level != InitLevel::Scene || plugin.is_some() {
+        let register_cpp = if has_regions {
+            register_cpp.to_string()
+        } else {
+            info!("Adding the gdsetup markers to register_types.cpp to register '{}' at the {} level", class_name, level);
+            sync::add_markers(register_cpp)?
+        };
+        let trimmed = |lines: Vec<String>| lines.iter().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect::<Vec<String>>();
+        let mut includes = trimmed(regions::read_region(&register_cpp, "includes").unwrap_or_default());
+        let mut registrations = level::parse_registrations(&regions::read_region(&register_cpp, "registrations").unwrap_or_default());
+        if !includes.contains(&include) {
+            includes.push(include);
+        };
+        let registered = registrations.entry(level).or_default();
+        for line in [Some(registration), plugin].into_iter().flatten() {
+            if !registered.contains(&line) {
+                registered.push(line);
+            };
+        };
+        return level::set_registrations(&register_cpp, &includes, &registrations);
+    };
+
+    let mut lines = register_cpp.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    if !lines.iter().any(|l| l.trim() == include) {
+        match lines.iter().rposition(|l| l.starts_with("#include \"")) {
+            Some(idx) => lines.insert(idx + 1, include),
+            None => warn!("Could not find where to put '{}' in register_types.cpp, please add it yourself.", include),
+        };
+    };
+    if !lines.iter().any(|l| l.trim() == registration) {
+        match lines.iter().rposition(|l| l.contains("ClassDB::register_class<") || l.trim_start().starts_with("GDREGISTER_")) {
+            Some(idx) => {
+                let indent = lines[idx].chars().take_while(|c| c.is_whitespace()).collect::<String>();
+                lines.insert(idx + 1, format!("{}{}", indent, registration));
+            },
+            None => warn!("Could not find where to put '{}' in register_types.cpp, please add it yourself.", registration),
+        };
+    };
+
+    Ok(regions::join_lines(lines, register_cpp))
+}
+
+
+fn build(ws: &mut Workspace, path: &Path) {
+    match ws.run(path, "scons") {
+        Ok(Some(output)) => _ = print_output(output),
+        Ok(None) => (),
+        Err(err) => debug!("Could not run 'scons': {}", err),
+    };
+}
@@ -0,0 +1,375 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Error, Context};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{codegen, manifest::Manifest, naming, workspace::Workspace};
+
+
+/// Name of the file 'godot --dump-extension-api' writes
+pub const API_FILE: &str = "extension_api.json";
+/// Where godot-cpp keeps the extension_api.json of the Godot version it targets
+const GODOT_CPP_API_FILE: &str = "godot-cpp/gdextension/extension_api.json";
+/// Snapshot of the Godot 4.3 classes (without their methods), singletons and global enums. Used when the project has no extension_api.json
+const BUNDLED_API: &str = include_str!("../data/extension_api.json");
+/// Editor classes whose name does not contain 'Editor', for snapshots without 'api_type'
+const EDITOR_CLASSES: [&str; 2] = ["FileSystemDock", "ScriptCreateDialog"];
+
+
+/// The parts of an extension_api.json gdsetup needs, everything else is ignored
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ExtensionApi {
+    #[serde(default)]
+    pub header: ApiHeader,
+    /// Variant types like 'String' or 'Vector2'
+    #[serde(default)]
+    pub builtin_classes: Vec<ApiBuiltinClass>,
+    #[serde(default)]
+    pub classes: Vec<ApiClass>,
+    #[serde(default)]
+    pub singletons: Vec<ApiSingleton>,
+    #[serde(default)]
+    pub global_enums: Vec<ApiEnum>,
+    /// Whether this is the snapshot which ships with gdsetup, it only knows Godot 4.3 and no methods
+    #[serde(skip)]
+    pub bundled: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ApiHeader {
+    pub version_major: u32,
+    pub version_minor: u32,
+    pub version_full_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiBuiltinClass {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiClass {
+    pub name: String,
+    pub inherits: Option<String>,
+    /// 'core' or 'editor', editor classes only exist inside the editor
+    pub api_type: Option<String>,
+    #[serde(default)]
+    pub is_refcounted: bool,
+    #[serde(default)]
+    pub methods: Vec<ApiMethod>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiMethod {
+    pub name: String,
+    #[serde(default)]
+    pub is_const: bool,
+    #[serde(default)]
+    pub is_virtual: bool,
+    pub return_value: Option<ApiValue>,
+    #[serde(default)]
+    pub arguments: Vec<ApiValue>,
+}
+
+/// An argument or return value. Return values don't have a name
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiValue {
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "type")]
+    pub value_type: String,
+    /// Exact width of ints and floats, e.g. 'int32' or 'double'
+    pub meta: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiSingleton {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub class: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiEnum {
+    pub name: String,
+}
+
+
+/// A virtual method of an engine class with the C++ types godot-cpp uses, ready to be overridden
+#[derive(Serialize, Debug, Clone)]
+pub struct VirtualMethod {
+    pub name: String,
+    pub return_type: String,
+    /// Parameter list of the declaration, e.g. 'const Ref<InputEvent> &p_event'
+    pub params: String,
+    pub is_const: bool,
+    /// Headers the argument and return types need
+    pub includes: Vec<String>,
+}
+
+
+impl ExtensionApi {
+    /// The snapshot which ships with gdsetup
+    pub fn bundled() -> Self {
+        let mut api: ExtensionApi = serde_json::from_str(BUNDLED_API).expect("The bundled extension_api.json is valid");
+        api.bundled = true;
+        api
+    }
+
+    /// Returns the extension_api.json of the project at 'root', if it has one. The manifest can point to a dumped file,
+    /// otherwise 'extension_api.json' at the root and the one of godot-cpp are used.
+    pub fn find(ws: &Workspace, root: &Path, manifest: Option<&Manifest>) -> Option<PathBuf> {
+        let configured = manifest.and_then(|m| m.project.extension_api.as_deref()).map(|file| root.join(file));
+        configured.into_iter()
+            .chain([root.join(API_FILE), root.join(GODOT_CPP_API_FILE)])
+            .find(|path| ws.exists(path))
+    }
+
+    /// Reads the extension_api.json of the project at 'root' and falls back to the bundled snapshot
+    pub fn load(ws: &Workspace, root: &Path, manifest: Option<&Manifest>) -> Result<Self, Error> {
+        let Some(path) = ExtensionApi::find(ws, root, manifest) else {
+            debug!("No {} found inside '{}', using the bundled one", API_FILE, root.display());
+            return Ok(ExtensionApi::bundled());
+        };
+        ExtensionApi::read(ws, &path)
+    }
+
+    pub fn read(ws: &Workspace, path: &Path) -> Result<Self, Error> {
+        debug!("Using '{}'", path.display());
+        let contents = ws.read_to_string(path).with_context(|| format!("Tried reading '{}'", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("Tried parsing '{}'", path.display()))
+    }
+
+    pub fn find_class(&self, name: &str) -> Option<&ApiClass> {
+        self.classes.iter().find(|c| c.name == name)
+    }
+
+    /// Whether 'class' is 'ancestor' or inherits from it. Classes the API does not know inherit from nothing
+    pub fn inherits_from(&self, class: &str, ancestor: &str) -> bool {
+        let mut current = self.find_class(class);
+        while let Some(class) = current {
+            if class.name == ancestor {
+                return true;
+            };
+            current = class.inherits.as_deref().and_then(|parent| self.find_class(parent));
+        }
+        false
+    }
+
+    /// Whether the engine class 'name' only exists inside the editor. Without an 'api_type' the name decides
+    pub fn is_editor_class(&self, name: &str) -> bool {
+        match self.find_class(name).and_then(|class| class.api_type.as_deref()) {
+            Some(api_type) => api_type == "editor",
+            None => name.contains("Editor") || EDITOR_CLASSES.contains(&name),
+        }
+    }
+
+    /// Refuses C++ class names which Godot already uses for a class or singleton. Global enums only cause a warning,
+    /// they just make unqualified uses ambiguous.
+    pub fn check_class_name(&self, classname: &str) -> Result<(), Error> {
+        let godot = match &self.header.version_full_name {
+            Some(name) => name.clone(),
+            None => format!("Godot {}.{}", self.header.version_major, self.header.version_minor),
+        };
+        if self.find_class(classname).is_some() || self.builtin_classes.iter().any(|c| c.name == classname) {
+            return Err(Error::msg(format!("'{}' can't be used as a class name, {} already has a class with that name.", classname, godot)));
+        };
+        if self.singletons.iter().any(|s| s.name == classname) {
+            return Err(Error::msg(format!("'{}' can't be used as a class name, {} already has a singleton with that name.", classname, godot)));
+        };
+        if self.global_enums.iter().any(|e| e.name == classname) {
+            warn!("{} has a global enum named '{}', you might have to write 'godot::{}' to use it.", godot, classname, classname);
+        };
+        Ok(())
+    }
+
+    /// Generated classes can only inherit from engine classes. The bundled snapshot might not know the classes of other
+    /// Godot versions, so it only warns.
+    pub fn check_base_class(&self, base: &str) -> Result<(), Error> {
+        if self.find_class(base).is_some() {
+            return Ok(());
+        };
+        let message = format!("'{}' is not a Godot class, common base classes are: {}.", base, codegen::BASE_CLASSES.join(", "));
+        if self.bundled {
+            warn!("{} The bundled Godot 4.3 snapshot might not know it, run 'godot --dump-extension-api' inside the project to check against your Godot version.", message);
+            return Ok(());
+        };
+        Err(Error::msg(message))
+    }
+
+    /// Looks up the virtual method 'name' of 'base' or one of the classes it inherits from
+    pub fn find_virtual(&self, base: &str, name: &str) -> Result<VirtualMethod, Error> {
+        if self.bundled {
+            return Err(Error::msg(format!("Overriding '{}' needs the extension_api.json of your Godot version. Run 'godot --dump-extension-api' inside the project (or set 'extension_api' in gdsetup.toml).", name)));
+        };
+        let mut class = self.find_class(base).with_context(|| format!("The extension_api.json does not know the class '{}'.", base))?;
+        loop {
+            if let Some(method) = class.methods.iter().find(|m| m.name == name) {
+                if !method.is_virtual {
+                    return Err(Error::msg(format!("'{}::{}' is not a virtual method and can't be overridden.", class.name, name)));
+                };
+                return Ok(self.get_virtual_method(method));
+            };
+            match class.inherits.as_deref().and_then(|parent| self.find_class(parent)) {
+                Some(parent) => class = parent,
+                None => return Err(Error::msg(format!("'{}' has no virtual method named '{}'.", base, name))),
+            };
+        }
+    }
+
+    fn get_virtual_method(&self, method: &ApiMethod) -> VirtualMethod {
+        let mut includes = vec![];
+        let return_type = match &method.return_value {
+            Some(value) => self.get_cpp_type(value, false, &mut includes),
+            None => "void".to_string(),
+        };
+        let params = method.arguments.iter()
+            .map(|arg| {
+                let arg_type = self.get_cpp_type(arg, true, &mut includes);
+                // Pointers and references are written next to the name, like godot-cpp does
+                match arg_type.strip_suffix(" *").or_else(|| arg_type.strip_suffix(" &")) {
+                    Some(stripped) => format!("{} {}p_{}", stripped, &arg_type[stripped.len() + 1..], arg.name),
+                    None => format!("{} p_{}", arg_type, arg.name),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        includes.sort();
+        includes.dedup();
+        VirtualMethod { name: method.name.clone(), return_type, params, is_const: method.is_const, includes }
+    }
+
+    /// Returns the C++ type godot-cpp uses for a type of the API. Arguments take Variants and objects by reference.
+    fn get_cpp_type(&self, value: &ApiValue, is_argument: bool, includes: &mut Vec<String>) -> String {
+        let by_reference = |cpp_type: String| if is_argument { format!("const {} &", cpp_type) } else { cpp_type };
+        let value_type = value.value_type.as_str();
+        match value_type {
+            "bool" => "bool".to_string(),
+            "int" => match value.meta.as_deref() {
+                // 'int32' -> 'int32_t', 'char16' -> 'char16_t'
+                Some(meta) => format!("{}_t", meta),
+                None => "int64_t".to_string(),
+            },
+            "float" => match value.meta.as_deref() {
+                Some("float") => "float".to_string(),
+                _ => "double".to_string(),
+            },
+            "Variant" => {
+                includes.push("godot_cpp/variant/variant.hpp".to_string());
+                by_reference("Variant".to_string())
+            },
+            _ if value_type.starts_with("enum::") || value_type.starts_with("bitfield::") => {
+                let (kind, name) = value_type.split_once("::").unwrap();
+                let cpp_type = match name.split_once('.') {
+                    Some((owner, enum_name)) => {
+                        self.add_include(owner, includes);
+                        format!("{}::{}", owner, enum_name)
+                    },
+                    None => name.to_string(),
+                };
+                if kind == "bitfield" { format!("BitField<{}>", cpp_type) } else { cpp_type }
+            },
+            _ if value_type.starts_with("typedarray::") => {
+                let element = &value_type["typedarray::".len()..];
+                includes.push("godot_cpp/variant/typed_array.hpp".to_string());
+                self.add_include(element, includes);
+                by_reference(format!("TypedArray<{}>", element))
+            },
+            _ if self.builtin_classes.iter().any(|c| c.name == value_type) => {
+                self.add_include(value_type, includes);
+                by_reference(value_type.to_string())
+            },
+            _ => match self.find_class(value_type) {
+                Some(class) if class.is_refcounted => {
+                    self.add_include(value_type, includes);
+                    includes.push("godot_cpp/classes/ref.hpp".to_string());
+                    by_reference(format!("Ref<{}>", value_type))
+                },
+                Some(_) => {
+                    self.add_include(value_type, includes);
+                    format!("{} *", value_type)
+                },
+                // Native structures and raw pointers (e.g. 'const void*') are used as they are
+                None => match value_type.strip_suffix('*') {
+                    Some(pointee) => format!("{} *", pointee.trim_end()),
+                    None => value_type.to_string(),
+                },
+            },
+        }
+    }
+
+    fn add_include(&self, type_name: &str, includes: &mut Vec<String>) {
+        if self.builtin_classes.iter().any(|c| c.name == type_name) {
+            includes.push(format!("godot_cpp/variant/{}.hpp", naming::to_snake_case(type_name)));
+        } else if self.find_class(type_name).is_some() {
+            includes.push(codegen::get_godot_include(type_name));
+        };
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = r#"{
+        "builtin_classes": [{ "name": "Vector2" }, { "name": "String" }],
+        "classes": [
+            { "name": "Object" },
+            { "name": "RefCounted", "inherits": "Object", "is_refcounted": true },
+            { "name": "Resource", "inherits": "RefCounted", "is_refcounted": true },
+            { "name": "InputEvent", "inherits": "Resource", "is_refcounted": true },
+            { "name": "Node", "inherits": "Object", "methods": [
+                { "name": "_input", "is_virtual": true, "arguments": [{ "name": "event", "type": "InputEvent" }] },
+                { "name": "_process", "is_virtual": true, "arguments": [{ "name": "delta", "type": "float", "meta": "double" }] },
+                { "name": "_notification", "is_virtual": true, "return_value": { "type": "int" }, "arguments": [{ "name": "what", "type": "int", "meta": "int32" }] },
+                { "name": "_get_targets", "is_virtual": true, "is_const": true, "return_value": { "type": "typedarray::Node" },
+                  "arguments": [{ "name": "parent", "type": "Node" }, { "name": "position", "type": "Vector2" }] },
+                { "name": "_set_mode", "is_virtual": true,
+                  "arguments": [{ "name": "mode", "type": "enum::Node.ProcessMode" }, { "name": "flags", "type": "bitfield::Node.ProcessThreadMessages" }, { "name": "error", "type": "enum::Error" }] },
+                { "name": "get_name", "return_value": { "type": "String" } }
+            ] },
+            { "name": "Node3D", "inherits": "Node" }
+        ]
+    }"#;
+
+    fn fixture() -> ExtensionApi {
+        serde_json::from_str(FIXTURE).unwrap()
+    }
+
+    #[test]
+    fn virtual_methods_use_the_types_of_godot_cpp() {
+        let api = fixture();
+        // Refcounted classes are passed as references to a Ref, the method is found on the parent class
+        let input = api.find_virtual("Node3D", "_input").unwrap();
+        assert_eq!((input.return_type.as_str(), input.params.as_str()), ("void", "const Ref<InputEvent> &p_event"));
+        assert_eq!(input.includes, vec!["godot_cpp/classes/input_event.hpp", "godot_cpp/classes/ref.hpp"]);
+
+        assert_eq!(api.find_virtual("Node", "_process").unwrap().params, "double p_delta");
+        let notification = api.find_virtual("Node", "_notification").unwrap();
+        assert_eq!((notification.return_type.as_str(), notification.params.as_str()), ("int64_t", "int32_t p_what"));
+
+        // Other objects are pointers, builtin types references and typed arrays are returned by value
+        let targets = api.find_virtual("Node", "_get_targets").unwrap();
+        assert_eq!(targets.return_type, "TypedArray<Node>");
+        assert_eq!(targets.params, "Node *p_parent, const Vector2 &p_position");
+        assert!(targets.is_const);
+        assert!(targets.includes.contains(&"godot_cpp/variant/typed_array.hpp".to_string()));
+        assert!(targets.includes.contains(&"godot_cpp/variant/vector2.hpp".to_string()));
+
+        let set_mode = api.find_virtual("Node", "_set_mode").unwrap();
+        assert_eq!(set_mode.params, "Node::ProcessMode p_mode, BitField<Node::ProcessThreadMessages> p_flags, Error p_error");
+        assert_eq!(set_mode.includes, vec!["godot_cpp/classes/node.hpp"]);
+    }
+
+    #[test]
+    fn only_virtual_methods_can_be_overridden() {
+        let api = fixture();
+        assert!(api.find_virtual("Node", "get_name").is_err());
+        assert!(api.find_virtual("Node", "_ready").is_err());
+        assert!(api.find_virtual("Spatial", "_input").is_err());
+        assert!(ExtensionApi::bundled().find_virtual("Node", "_input").is_err());
+        assert!(api.inherits_from("InputEvent", "RefCounted") && !api.inherits_from("Node3D", "Resource"));
+    }
+}
@@ -0,0 +1,433 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Error, Context};
+use log::{info, debug, warn};
+use minijinja::{context, Value};
+
+use crate::{BindArgs, codegen, header::{self, ClassDeclaration, Signature}, manifest::Manifest, members, sync, templates::Templates, version::GodotVersion, workspace::Workspace};
+
+
+/// Starts an annotation inside of a comment, e.g. '// @gd.method' or '// @gd.property(hint=range, "0,10")'
+pub const ANNOTATION_PREFIX: &str = "@gd.";
+/// The kinds of annotations, each one binds the declaration following it
+pub const ANNOTATIONS: [&str; 5] = ["method", "property", "signal", "enum", "constant"];
+/// First line of the generated files, others are never removed
+pub const GENERATED_COMMENT: &str = "// Generated by gdsetup";
+
+
+/// An annotation like '// @gd.property(hint=range, "0,10")'. 'text' is whatever follows it, e.g. the signature of a signal
+struct Annotation {
+    kind: String,
+    args: Vec<String>,
+    text: String,
+    line: usize,
+}
+
+/// The lines of a generated '_bind_methods'. Methods are bound first, properties need their setters and getters
+#[derive(Default)]
+struct ClassBindings {
+    /// Names and bindings of the annotated methods
+    methods: Vec<(String, String)>,
+    /// Names and bindings of the setters and getters of properties
+    accessors: Vec<(String, String)>,
+    properties: Vec<String>,
+    signals: Vec<String>,
+    constants: Vec<String>,
+    /// Methods which emit their signal, they are defined inside the generated file
+    emitters: Vec<Signature>,
+}
+
+
+/// The file a header's '_bind_methods' are generated into, e.g. 'player.gen.cpp' for 'player.h'
+pub fn get_generated_path(header: &Path) -> PathBuf {
+    header.with_extension("gen.cpp")
+}
+
+
+pub fn bind(ws: &mut Workspace, bindargs: BindArgs, src_dir: &str) -> Result<(), Error> {
+    let path = match bindargs.path {
+        Some(path) => path,
+        None => std::env::current_dir().with_context(|| "Getting current directory.")?,
+    };
+
+    ws.set_root(&path);
+    let manifest = Manifest::load(ws, &path)?;
+    let src_dir = match &manifest {
+        Some(manifest) => manifest.project.src_dir.clone(),
+        None => src_dir.to_string(),
+    };
+    let version = manifest.as_ref().map(|m| m.project.godot_version).unwrap_or_default();
+    let templates = Templates::load(ws, &path)?;
+
+    let headers = match &bindargs.class {
+        Some(class) => vec![members::find_class_files(ws, &path, class, &src_dir, manifest.as_ref())?.header],
+        None => {
+            let mut module_folders = vec![];
+            sync::find_module_folders(ws, &path.join(&src_dir), &mut module_folders)?;
+            let mut headers = vec![];
+            for folder in &module_folders {
+                sync::find_headers(ws, folder, &mut headers)?;
+            };
+            headers
+        },
+    };
+
+    let mut generated = 0;
+    for header in &headers {
+        let contents = ws.read_to_string(header).with_context(|| format!("Tried reading '{}'", header.display()))?;
+        let Some(bindings) = bind_header(&contents, version).with_context(|| format!("Could not bind the annotations of '{}'", header.display()))? else {
+            remove_generated(ws, header)?;
+            continue;
+        };
+        check_bind_methods(ws, header, &bindings)?;
+
+        let file_name = header.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let classes = bindings.into_iter()
+            .map(|(class, bindings)| context! {
+                bindings => bindings.lines(),
+                emitters => bindings.emitters.iter().map(|signature| get_emitter(&class.name, signature)).collect::<Vec<Value>>(),
+                name => class.name,
+            })
+            .collect::<Vec<Value>>();
+        let generated_path = get_generated_path(header);
+        let code = codegen::generate_class_gen_cpp(&templates, file_name, classes, version)?;
+        if ws.exists(&generated_path) && ws.read_to_string(&generated_path)? == code {
+            debug!("'{}' is up to date", generated_path.display());
+        } else {
+            info!("Generating '{}'", generated_path.display());
+            ws.write(&generated_path, code).with_context(|| format!("Tried writing '{}'", generated_path.display()))?;
+        };
+        generated += 1;
+    };
+
+    match &bindargs.class {
+        Some(class) if generated == 0 => Err(Error::msg(format!("'{}' has no '// {}*' annotations.", class, ANNOTATION_PREFIX))),
+        _ => {
+            info!("Generated the bindings of {} headers", generated);
+            Ok(())
+        },
+    }
+}
+
+
+/// Returns the bindings of every annotated class of a header, None if it has no annotations
+fn bind_header(contents: &str, version: GodotVersion) -> Result<Option<Vec<(ClassDeclaration, ClassBindings)>>, Error> {
+    let lines = contents.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    let classes = header::find_class_declarations(&lines);
+    let mut bound = vec![];
+    for class in classes {
+        let body = &lines[class.start..class.end];
+        if !body.iter().any(|l| l.trim().starts_with("GDCLASS(")) {
+            continue;
+        };
+        let mut annotations = vec![];
+        for (idx, line) in lines.iter().enumerate().take(class.end).skip(class.start) {
+            if let Some(annotation) = parse_annotation(line, idx)? {
+                annotations.push(annotation);
+            };
+        };
+        if annotations.is_empty() {
+            continue;
+        };
+        let bindings = bind_class(&lines, &class, &annotations, version)?;
+        bound.push((class, bindings));
+    };
+
+    // Annotations outside of a class have nothing to bind
+    for (idx, line) in lines.iter().enumerate() {
+        if !bound.iter().any(|(class, _)| (class.start..class.end).contains(&idx)) && parse_annotation(line, idx)?.is_some() {
+            warn!("Ignoring the annotation in line {}, it is not inside of a class with 'GDCLASS'", idx + 1);
+        };
+    };
+    Ok(Some(bound).filter(|bound| !bound.is_empty()))
+}
+
+
+fn bind_class(lines: &[String], class: &ClassDeclaration, annotations: &[Annotation], version: GodotVersion) -> Result<ClassBindings, Error> {
+    let mut bindings = ClassBindings::default();
+    for annotation in annotations {
+        let context = || format!("Could not bind the '{}{}' annotation in line {}", ANNOTATION_PREFIX, annotation.kind, annotation.line + 1);
+        let declaration = get_declaration(lines, annotation.line + 1, class.end);
+        match annotation.kind.as_str() {
+            "method" => {
+                let (declaration, _) = declaration.with_context(|| "It is not followed by a method declaration.").with_context(context)?;
+                let signature = header::parse_method_declaration(&declaration).with_context(context)?;
+                if bindings.methods.iter().any(|(name, _)| *name == signature.name) {
+                    return Err(Error::msg(format!("'{}' binds the method '{}' twice, overloads are not supported.", class.name, signature.name)));
+                };
+                bindings.methods.push((signature.name.clone(), members::get_method_binding(&class.name, &signature)));
+            },
+            "property" => bind_property(lines, class, annotation, declaration, &mut bindings).with_context(context)?,
+            "signal" => bind_signal(annotation, declaration, &mut bindings).with_context(context)?,
+            "enum" => {
+                let (declaration, last) = declaration.with_context(|| "It is not followed by an enum.").with_context(context)?;
+                bindings.constants.extend(bind_enum(lines, class, annotation, &declaration, last, version).with_context(context)?);
+            },
+            "constant" => {
+                let (declaration, _) = declaration.with_context(|| "It is not followed by a constant.").with_context(context)?;
+                let constant = header::parse_member_declaration(&declaration).with_context(context)?;
+                if !constant.param_type.split_whitespace().any(|word| word == "static") || constant.default.is_none() {
+                    return Err(Error::msg(format!("'{}' is not a static constant, e.g. 'static constexpr int64_t {} = 1;'.", declaration, constant.name))).with_context(context);
+                };
+                bindings.constants.push(format!("BIND_CONSTANT({});", constant.name));
+            },
+            other => return Err(Error::msg(format!("'{}{}' is not an annotation, use one of: {}.", ANNOTATION_PREFIX, other,
+                ANNOTATIONS.map(|a| format!("{}{}", ANNOTATION_PREFIX, a)).join(", ")))).with_context(context),
+        };
+    };
+    Ok(bindings)
+}
+
+
+/// Binds a member variable, e.g. 'float speed = 1.0;' with 'set_speed' and 'get_speed' (or the ones given by 'setter=' and 'getter=')
+fn bind_property(lines: &[String], class: &ClassDeclaration, annotation: &Annotation, declaration: Option<(String, usize)>, bindings: &mut ClassBindings) -> Result<(), Error> {
+    let (declaration, _) = declaration.with_context(|| "It is not followed by a member variable.")?;
+    let member = header::parse_member_declaration(&declaration)?;
+    let member_type = member.param_type.as_str();
+    if member_type.split_whitespace().any(|word| word == "static" || word == "const" || word == "constexpr") {
+        return Err(Error::msg(format!("'{}' can't be a property, it is static or const.", member.name)));
+    };
+
+    let (mut hint, mut hint_string) = (None, None);
+    let (mut setter, mut getter) = (format!("set_{}", member.name), format!("get_{}", member.name));
+    for arg in &annotation.args {
+        match arg.split_once('=') {
+            Some(("hint", value)) => hint = Some(members::get_property_hint(value.trim())?),
+            Some(("hint_string", value)) => hint_string = Some(unquote(value)),
+            Some(("setter", value)) => setter = value.trim().to_string(),
+            Some(("getter", value)) => getter = value.trim().to_string(),
+            _ if arg.starts_with('"') => hint_string = Some(unquote(arg)),
+            _ => return Err(Error::msg(format!("Unknown argument '{}', use 'hint=', 'hint_string=' (or just a string), 'setter=' or 'getter='.", arg))),
+        };
+    };
+    // Resources get picked by type inside the inspector
+    let variant_type = match member_type.strip_prefix("Ref<").and_then(|t| t.strip_suffix('>')) {
+        Some(resource) => {
+            if hint.is_none() {
+                hint = Some("PROPERTY_HINT_RESOURCE_TYPE".to_string());
+                hint_string = Some(resource.trim().to_string());
+            };
+            "OBJECT"
+        },
+        None if member_type == "Variant" || member_type == "Object" => {
+            return Err(Error::msg(format!("Properties of the type '{}' are not supported, use a concrete type like 'float' or 'String'.", member_type)));
+        },
+        None => members::get_variant_type(member_type)?,
+    };
+    if hint.is_none() && hint_string.is_some() {
+        return Err(Error::msg("A hint string needs a hint, e.g. '@gd.property(hint=range, \"0,100,1\")'."));
+    };
+
+    for accessor in [&setter, &getter] {
+        let signature = find_method(lines, class, accessor)?
+            .with_context(|| format!("The property '{}' needs the method '{}', declare it inside of '{}' or name another one with '{}property(setter=..., getter=...)'.",
+                member.name, accessor, class.name, ANNOTATION_PREFIX))?;
+        if !bindings.accessors.iter().any(|(name, _)| name == accessor) {
+            bindings.accessors.push((accessor.clone(), members::get_method_binding(&class.name, &signature)));
+        };
+    };
+    let property_info = members::format_property_info(variant_type, &member.name, hint.as_deref().unwrap_or("PROPERTY_HINT_NONE"), hint_string.as_deref().unwrap_or_default());
+    bindings.properties.push(format!("ADD_PROPERTY({}, \"{}\", \"{}\");", property_info, setter, getter));
+    Ok(())
+}
+
+
+/// Binds '// @gd.signal died(int cause)'. Without a signature the annotated method declaration becomes the signal,
+/// calling it emits the signal
+fn bind_signal(annotation: &Annotation, declaration: Option<(String, usize)>, bindings: &mut ClassBindings) -> Result<(), Error> {
+    if !annotation.text.is_empty() {
+        let signature = members::parse_signature(&annotation.text)?;
+        bindings.signals.push(members::get_signal_binding(&signature)?);
+        return Ok(());
+    };
+    let (declaration, _) = declaration.with_context(|| format!("It is neither followed by a signature (e.g. '{}signal died(int cause)') nor a method declaration.", ANNOTATION_PREFIX))?;
+    let signature = header::parse_method_declaration(&declaration)?;
+    bindings.signals.push(members::get_signal_binding(&signature)?);
+    bindings.emitters.push(signature);
+    Ok(())
+}
+
+
+/// The definition of a method which emits the signal of the same name
+fn get_emitter(classname: &str, signature: &Signature) -> Value {
+    let params = signature.params.iter().map(|p| header::format_param(p, false)).collect::<Vec<String>>().join(", ");
+    let args = signature.params.iter().map(|p| format!(", {}", p.name)).collect::<String>();
+    context! {
+        signature => format!("void {}::{}({})", classname, signature.name, params),
+        args => format!("\"{}\"{}", signature.name, args),
+    }
+}
+
+
+/// Binds the values of 'enum State { IDLE, RUNNING };', '@gd.enum(bitfield)' binds them as flags
+fn bind_enum(lines: &[String], class: &ClassDeclaration, annotation: &Annotation, declaration: &str, last: usize, version: GodotVersion) -> Result<Vec<String>, Error> {
+    let name = declaration.strip_prefix("enum ")
+        .filter(|rest| !rest.starts_with("class ") && !rest.starts_with("struct "))
+        .and_then(|rest| rest.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').next())
+        .filter(|name| !name.is_empty())
+        .with_context(|| format!("'{}' is not a named enum, scoped enums ('enum class') can't be bound.", declaration))?;
+    let is_bitfield = annotation.args.iter().any(|arg| arg == "bitfield");
+    let (cast, bind) = if is_bitfield { ("VARIANT_BITFIELD_CAST", "BIND_BITFIELD_FLAG") } else { ("VARIANT_ENUM_CAST", "BIND_ENUM_CONSTANT") };
+    let cast_line = members::get_enum_cast(cast, &class.name, name, version);
+    if !lines.iter().skip(class.end).any(|l| l.trim() == cast_line) {
+        return Err(Error::msg(format!("The enum '{}' needs '{}' after the declaration of '{}'.", name, cast_line, class.name)));
+    };
+
+    // The values are everything between the braces
+    let mut body = String::new();
+    for line in lines.iter().take(class.end).skip(annotation.line + 1) {
+        body.push_str(strip_comment(line));
+        body.push('\n');
+        if body.contains('{') && body.contains('}') {
+            break;
+        };
+    };
+    let values = body.split_once('{')
+        .and_then(|(_, rest)| rest.split_once('}'))
+        .map(|(values, _)| values)
+        .with_context(|| format!("The enum '{}' ending in line {} has no values.", name, last + 1))?;
+    let bindings = values.split(',')
+        .map(|value| value.split('=').next().unwrap_or_default().trim())
+        .filter(|value| !value.is_empty())
+        .map(|value| format!("{}({});", bind, value))
+        .collect::<Vec<String>>();
+    if bindings.is_empty() {
+        return Err(Error::msg(format!("The enum '{}' has no values.", name)));
+    };
+    Ok(bindings)
+}
+
+
+/// Returns the annotation of a line, if it has one
+fn parse_annotation(line: &str, idx: usize) -> Result<Option<Annotation>, Error> {
+    let Some(rest) = line.trim().strip_prefix("//").and_then(|comment| comment.trim_start().strip_prefix(ANNOTATION_PREFIX)) else {
+        return Ok(None);
+    };
+    let kind_end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+    let (kind, rest) = rest.split_at(kind_end);
+    let (args, text) = match rest.starts_with('(') {
+        true => {
+            let close = header::find_closing_parenthesis(rest, 0)
+                .with_context(|| format!("The annotation in line {} is missing a closing ')'.", idx + 1))?;
+            (header::split_params(&rest[1..close]), rest[close + 1..].trim())
+        },
+        false => (vec![], rest.trim()),
+    };
+    Ok(Some(Annotation { kind: kind.to_string(), args, text: text.to_string(), line: idx }))
+}
+
+
+/// Returns the declaration starting at or after 'from' and the line it ends in. Comments and access specifiers are skipped,
+/// a declaration ends with ';' or the '{' of a body
+fn get_declaration(lines: &[String], from: usize, end: usize) -> Option<(String, usize)> {
+    let first = (from..end).find(|idx| {
+        let line = strip_comment(&lines[*idx]);
+        !line.is_empty() && !matches!(line, "public:" | "private:" | "protected:")
+    })?;
+    let mut declaration = String::new();
+    for (idx, line) in lines.iter().enumerate().take(end).skip(first) {
+        let line = strip_comment(line);
+        declaration.push_str(line);
+        declaration.push(' ');
+        if line.ends_with(';') || line.contains('{') {
+            return Some((declaration.trim().to_string(), idx));
+        };
+    };
+    None
+}
+
+
+/// Returns the declaration of a method of the class, e.g. of the setter of a property
+fn find_method(lines: &[String], class: &ClassDeclaration, name: &str) -> Result<Option<Signature>, Error> {
+    for idx in class.start + 1..class.end {
+        let line = strip_comment(&lines[idx]);
+        let Some(position) = line.find(&format!("{}(", name)) else {
+            continue;
+        };
+        if line[..position].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+            continue;
+        };
+        if let Some((declaration, _)) = get_declaration(lines, idx, class.end) {
+            let signature = header::parse_method_declaration(&declaration)
+                .with_context(|| format!("Could not read the declaration of '{}' in line {}", name, idx + 1))?;
+            return Ok(Some(signature));
+        };
+    };
+    Ok(None)
+}
+
+
+/// Removes a trailing '//' comment from a line of code, '//' inside of strings (e.g. "res://") is kept
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut previous = ' ';
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '/' if previous == '/' && !in_string => return line[..idx - 1].trim(),
+            _ => (),
+        };
+        previous = c;
+    };
+    line.trim()
+}
+
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value).to_string()
+}
+
+
+impl ClassBindings {
+    /// Every line of '_bind_methods'. Setters and getters which are annotated methods as well are bound once
+    fn lines(&self) -> Vec<String> {
+        let mut lines = self.methods.iter().map(|(_, binding)| binding.clone()).collect::<Vec<String>>();
+        lines.extend(self.accessors.iter()
+            .filter(|(name, _)| !self.methods.iter().any(|(method, _)| method == name))
+            .map(|(_, binding)| binding.clone()));
+        lines.extend(self.properties.iter().cloned());
+        lines.extend(self.signals.iter().cloned());
+        lines.extend(self.constants.iter().cloned());
+        lines
+    }
+}
+
+
+/// The generated file defines '_bind_methods', the class' own source file must not define it as well
+fn check_bind_methods(ws: &Workspace, header: &Path, bindings: &[(ClassDeclaration, ClassBindings)]) -> Result<(), Error> {
+    let source_path = header.with_extension("cpp");
+    if !ws.exists(&source_path) {
+        return Ok(());
+    };
+    let source = ws.read_to_string(&source_path).with_context(|| format!("Tried reading '{}'", source_path.display()))?;
+    for (class, class_bindings) in bindings {
+        if source.contains(&format!("{}::_bind_methods()", class.name)) {
+            return Err(Error::msg(format!("'{}' still defines '{}::_bind_methods', remove it (and annotate the members it binds) so that '{}' can define it.",
+                source_path.display(), class.name, get_generated_path(header).display())));
+        };
+        for emitter in &class_bindings.emitters {
+            if source.contains(&format!("{}::{}(", class.name, emitter.name)) {
+                return Err(Error::msg(format!("'{}' defines the signal '{}::{}', remove the definition, calling it emits the signal.", source_path.display(), class.name, emitter.name)));
+            };
+        };
+    };
+    Ok(())
+}
+
+
+/// Removes the generated file of a header whose annotations are gone
+fn remove_generated(ws: &mut Workspace, header: &Path) -> Result<(), Error> {
+    let generated_path = get_generated_path(header);
+    if !ws.exists(&generated_path) {
+        return Ok(());
+    };
+    let generated = ws.read_to_string(&generated_path).with_context(|| format!("Tried reading '{}'", generated_path.display()))?;
+    if generated.starts_with(GENERATED_COMMENT) {
+        info!("Removing '{}', '{}' has no annotations anymore", generated_path.display(), header.display());
+        ws.remove_file(&generated_path)?;
+    } else {
+        debug!("Keeping '{}', it was not generated by gdsetup", generated_path.display());
+    };
+    Ok(())
+}
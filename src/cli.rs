@@ -2,8 +2,9 @@ use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand};
 
+use crate::{codegen, level, version};
+
 
-#[cfg_attr(name, String)]
 #[derive(Parser, Debug)]
 #[command(about = "Sets up a Godot C++ project.", long_about = None)]
 #[command(author, version, about, long_about = None)]
@@ -22,15 +23,23 @@ pub struct GDSetup {
         value_parser = clap::builder::PossibleValuesParser::new(["error", "warn", "info", "debug", "trace"]),
     )]
     pub log_level: String,
+
+    /// Only print what would be created, modified, moved, deleted and run without touching the disk
+    #[arg(long = "dry-run", default_value_t = false, global = true)]
+    pub dry_run: bool,
 }
 
 #[derive(Args, Debug)]
 pub struct RenameArgs {
-    /// Name of the GDExtension module you want to rename
+    /// Name of the GDExtension module (or class with --class) you want to rename
     pub old_modulename: String,
     /// New name
     pub new_modulename: String,
 
+    /// Rename a class (its files, C++ class and registration) instead of a module
+    #[arg(long = "class", short = 'c', default_value_t = false)]
+    pub class: bool,
+
     /// Path to an existing Godot GDExtension project folder
     #[arg(long = "path", short = 'p', value_name = "PATH")]
     pub path: Option<PathBuf>,
@@ -42,7 +51,189 @@ pub struct RenameArgs {
 
 #[derive(Args, Debug)]
 pub struct AddArgs {
+    /// Optional: Shorthand version for 'gdsetup add module [NAME]'
+    pub name: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<AddCommands>,
+
+    /// Path to an existing Godot GDExtension project folder
+    #[arg(long = "path", short = 'p', value_name = "PATH", global = true)]
+    pub path: Option<PathBuf>,
+
+    /// Whether to NOT use the 'scons' command to instantly build the project once something has been added.
+    #[arg(long = "no-build", short = 'b', default_value_t = false, global = true)]
+    pub nobuild: bool,
+
+    /// The Godot class generated classes inherit from, e.g. Node3D, Resource or CharacterBody3D
+    #[arg(
+        long = "base",
+        value_name = "GODOT CLASS",
+        default_value_t = String::from(codegen::DEFAULT_BASE_CLASS),
+        global = true,
+    )]
+    pub base: String,
+}
+
+#[derive(Args, Debug)]
+pub struct AddModuleArgs {
+    /// Name of the new GDExtension module
     pub name: String,
+
+    /// Name of the class that gets created inside the module. Defaults to the name of the module
+    #[arg(long = "class", value_name = "NAME")]
+    pub class: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct AddClassArgs {
+    /// Name of the new class
+    pub name: String,
+
+    /// Name of the module the class should be added to. Defaults to the module created by 'gdsetup init'
+    #[arg(long = "module", short = 'm', value_name = "MODULE")]
+    pub module: Option<String>,
+
+    /// Virtual methods of the base class to override (e.g. '_ready,_input'). Needs the extension_api.json of your Godot version
+    #[arg(long = "override", value_name = "METHODS", value_delimiter = ',')]
+    pub overrides: Vec<String>,
+
+    /// Initialization level the class is registered at. Defaults to 'editor' for editor base classes like EditorPlugin, otherwise 'scene'
+    #[arg(
+        long = "level",
+        value_name = "LEVEL",
+        value_parser = clap::builder::PossibleValuesParser::new(level::INIT_LEVELS),
+    )]
+    pub level: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct AddMethodArgs {
+    /// Name of the class the method is added to (e.g. 'Player' or 'player')
+    pub class: String,
+
+    /// Signature of the method, e.g. 'get_health -> int' or 'set_speed(float speed = 1.0)'. Put it in quotes
+    pub signature: String,
+
+    /// Creates a static method, bound with 'bind_static_method'
+    #[arg(long = "static", default_value_t = false)]
+    pub is_static: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AddPropertyArgs {
+    /// Name of the class the property is added to (e.g. 'Player' or 'player')
+    pub class: String,
+
+    /// Name of the property, the methods are called 'set_NAME' and 'get_NAME'
+    pub name: String,
+
+    /// Type of the property, e.g. 'float', 'String' or 'Vector3'
+    #[arg(value_name = "TYPE")]
+    pub property_type: String,
+
+    /// How the inspector edits the property, e.g. 'range', 'enum' or 'file'
+    #[arg(long = "hint", value_name = "HINT")]
+    pub hint: Option<String>,
+
+    /// Details for the hint, e.g. '0,100,1' for a range or 'Easy,Hard' for an enum
+    #[arg(long = "hint-string", value_name = "STRING")]
+    pub hint_string: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct AddSignalArgs {
+    /// Name of the class the signal is added to (e.g. 'Player' or 'player')
+    pub class: String,
+
+    /// Name and arguments of the signal, e.g. 'died' or 'health_changed(int old_health, int new_health)'. Put it in quotes
+    pub signature: String,
+
+    /// Also creates the method 'emit_NAME' which emits the signal with its arguments
+    #[arg(long = "emit", default_value_t = false)]
+    pub emit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AddEnumArgs {
+    /// Name of the class the enum is added to (e.g. 'Player' or 'player')
+    pub class: String,
+
+    /// Name of the enum, e.g. 'State'
+    pub name: String,
+
+    /// Values of the enum, e.g. 'IDLE,RUNNING' or 'LOW=1,HIGH=10'
+    #[arg(value_name = "VALUES", value_delimiter = ',', required = true)]
+    pub values: Vec<String>,
+
+    /// Creates a bitfield whose flags can be combined. Flags without a value get the lowest power of two no other flag uses
+    #[arg(long = "bitfield", default_value_t = false)]
+    pub bitfield: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AddConstantArgs {
+    /// Name of the class the constant is added to (e.g. 'Player' or 'player')
+    pub class: String,
+
+    /// Name of the constant, e.g. 'MAX_HEALTH'
+    pub name: String,
+
+    /// Integer value of the constant
+    #[arg(allow_hyphen_values = true)]
+    pub value: String,
+}
+
+#[derive(Args, Debug)]
+pub struct UndoArgs {
+    /// Path to the Godot GDExtension project folder the last command ran in
+    #[arg(long = "path", short = 'p', value_name = "PATH")]
+    pub path: Option<PathBuf>,
+
+    /// Undo even if files have been edited since the last command, those edits are lost
+    #[arg(long = "force", short = 'f', default_value_t = false)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SyncArgs {
+    /// Path to an existing Godot GDExtension project folder
+    #[arg(long = "path", short = 'p', value_name = "PATH")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct BindArgs {
+    /// Only generate the bindings of this class (e.g. 'Player' or 'player'). Default: every annotated header
+    pub class: Option<String>,
+
+    /// Path to an existing Godot GDExtension project folder
+    #[arg(long = "path", short = 'p', value_name = "PATH")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct UpgradeArgs {
+    /// The Godot version the project should be upgraded to
+    #[arg(
+        long = "to",
+        value_name = "VERSION",
+        default_value_t = String::from(version::DEFAULT_VERSION),
+        value_parser = clap::builder::PossibleValuesParser::new(version::SUPPORTED_VERSIONS),
+    )]
+    pub to: String,
+
+    /// Path to an existing Godot GDExtension project folder
+    #[arg(long = "path", short = 'p', value_name = "PATH")]
+    pub path: Option<PathBuf>,
+
+    /// Apply the changes without showing them and asking first
+    #[arg(long = "yes", short = 'y', default_value_t = false)]
+    pub yes: bool,
+
+    /// Whether to NOT use the 'scons' command to instantly build the project once it has been upgraded.
+    #[arg(long = "no-build", short = 'b', default_value_t = false)]
+    pub nobuild: bool,
 }
 
 #[derive(Args, Debug)]
@@ -57,6 +248,47 @@ pub struct InitArgs {
     /// Whether to NOT use the 'scons' command to instantly build the project once it has been initialized.
     #[arg(long = "no-build", short = 'b', default_value_t = false)]
     pub nobuild: bool,
+
+    /// The Godot class generated classes inherit from, e.g. Node3D, Resource or CharacterBody3D
+    #[arg(
+        long = "base",
+        value_name = "GODOT CLASS",
+        default_value_t = String::from(codegen::DEFAULT_BASE_CLASS),
+    )]
+    pub base: String,
+
+    /// The Godot version the project targets. Selects the matching templates and godot-cpp branch
+    #[arg(
+        long = "godot-version",
+        value_name = "VERSION",
+        default_value_t = String::from(version::DEFAULT_VERSION),
+        value_parser = clap::builder::PossibleValuesParser::new(version::SUPPORTED_VERSIONS),
+    )]
+    pub godot_version: String,
+
+    /// Folder (relative to the project) which contains the Godot project
+    #[arg(long = "godot-dir", value_name = "DIR")]
+    pub godot_dir: Option<String>,
+
+    /// Folder (relative to the project) which contains the C++ sources
+    #[arg(long = "src-dir", value_name = "DIR")]
+    pub src_dir: Option<String>,
+
+    /// Name of the GDExtension module (the library) that gets created
+    #[arg(long = "module", value_name = "NAME")]
+    pub module: Option<String>,
+
+    /// Name of the class that gets created inside the module. Defaults to the name of the module
+    #[arg(long = "class", value_name = "NAME")]
+    pub class: Option<String>,
+
+    /// Template pack (a folder or git repository containing a gdsetup-template.toml) to create the project from
+    #[arg(long = "template", short = 't', value_name = "PATH OR GIT URL")]
+    pub template: Option<String>,
+
+    /// Value of a variable of the template pack, skips its prompt. Can be given multiple times
+    #[arg(long = "var", value_name = "NAME=VALUE")]
+    pub vars: Vec<String>,
 }
 
 
@@ -73,8 +305,16 @@ pub enum Commands {
     ///     'gdsetup init -p mynewproject'     - Longest form of above command
     /// 
     ///     'gdsetup init mynewproject -b'     - Creates a new folder named 'mynewproject' (+subfolders) but does NOT instantly build it
+    /// 
+    ///     'gdsetup init mynewproject --base Node2D'  - Creates a new folder named 'mynewproject' whose example class inherits from Node2D
+    /// 
+    ///     'gdsetup init mynewproject --godot-version 4.1'  - Creates a new folder named 'mynewproject' for Godot 4.1 (and godot-cpp 4.1)
+    /// 
+    ///     'gdsetup init mynewproject -t path/to/pack --var author=Me'  - Creates a new folder named 'mynewproject' from a template pack
+    /// 
+    ///     'gdsetup init mynewproject --godot-dir game --src-dir native --module mygame --class player'  - Uses a custom folder layout and names
     Init(InitArgs),
-    /// Renames all occurences of the extension name (or the files)
+    /// Renames all occurences of the module name (or, with --class, of a class name) and the files
     /// To do that, gdsetup tries to find certain files and functions it has generated upon running 'gdsetup init'.
     /// If you have changed function names or file names, this might not work as intended and manual work is needed.
     /// 
@@ -82,8 +322,90 @@ pub enum Commands {
     /// 
     ///     'gdsetup rename oldname newname -p path/to/project'             - Performs the renaming process inside the path/to/project folder
     /// 
-    ///     'gdsetup rename oldname newname -p path/to/project'             - Performs the renaming process inside the path/to/project folder WITHOUT building
+    ///     'gdsetup rename oldname newname -p path/to/project -b'          - Performs the renaming process inside the path/to/project folder WITHOUT building
+    /// 
+    ///     'gdsetup rename --class player enemy -p path/to/project'        - Renames the class 'Player' (and its files) to 'Enemy', the module keeps its name
     Rename(RenameArgs),
-    /// Creates another GDExtension module
+    /// Creates another GDExtension module next to the existing one(s) or adds a class to an existing module.
+    /// A new module gets its own .gdextension file, source folder (src/NAME/) and build target.
+    /// 
+    /// Examples:
+    /// 
+    ///     'gdsetup add mymodule -p path/to/project'                       - Adds the module 'mymodule' to the project inside path/to/project
+    /// 
+    ///     'gdsetup add module mymodule --class player -p path/to/project' - Adds the module 'mymodule' whose first class is 'Player'
+    /// 
+    ///     'gdsetup add class player -p path/to/project'                   - Adds the class 'Player' to the module created by 'gdsetup init'
+    /// 
+    ///     'gdsetup add class player --base Node3D -p path/to/project'     - Adds the class 'Player' which inherits from Node3D
+    /// 
+    ///     'gdsetup add class player --base Node3D --override _ready,_input' - Adds the class 'Player' which overrides '_ready' and '_input'
+    /// 
+    ///     'gdsetup add class my_plugin --base EditorPlugin'             - Adds the class 'MyPlugin' which is registered at the editor level
+    /// 
+    ///     'gdsetup add method player "set_speed(float speed = 1.0)"'     - Adds the method 'set_speed' to 'Player' and binds it with a default argument
+    /// 
+    ///     'gdsetup add property player speed float --hint range --hint-string "0,100,1"' - Adds the property 'speed' to 'Player'
+    /// 
+    ///     'gdsetup add signal player "health_changed(int health)" --emit' - Adds the signal 'health_changed' and 'emit_health_changed' to 'Player'
+    /// 
+    ///     'gdsetup add enum player State IDLE,RUNNING,JUMPING'          - Adds the enum 'State' to 'Player' and binds its values
+    /// 
+    ///     'gdsetup add constant player MAX_HEALTH 100'                  - Adds the constant 'MAX_HEALTH' to 'Player'
     Add(AddArgs),
+    /// Reverts all file changes of the last command that ran inside the project.
+    /// External commands (git, scons) can not be reverted.
+    /// 
+    /// Examples:
+    /// 
+    ///     'gdsetup undo -p path/to/project'                               - Reverts the last command which ran inside path/to/project
+    Undo(UndoArgs),
+    /// Migrates the generated code of a project to a newer Godot (and godot-cpp) version.
+    /// Rewrites the entry points in register_types.cpp, the [configuration] of the .gdextension files and SConstruct
+    /// and switches the godot-cpp submodule to the matching branch. The changes are shown before they are applied.
+    /// 
+    /// Examples:
+    /// 
+    ///     'gdsetup upgrade --to 4.3 -p path/to/project'                   - Upgrades the project inside path/to/project to Godot 4.3
+    /// 
+    ///     'gdsetup upgrade --to 4.3 -y -p path/to/project'                - Same as above, but does not ask before applying the changes
+    Upgrade(UpgradeArgs),
+    /// Registers every class of the project, including the ones written by hand.
+    /// Scans the headers inside the source folder for 'GDCLASS(Name, Base)' and rewrites the includes and registrations
+    /// between the '// gdsetup:begin' and '// gdsetup:end' markers of each register_types.cpp, base classes first.
+    /// Classes with pure virtual methods are registered as abstract, classes with 'GDVIRTUAL' methods as virtual.
+    /// 
+    /// Examples:
+    /// 
+    ///     'gdsetup sync -p path/to/project'                               - Registers all classes of the project inside path/to/project
+    Sync(SyncArgs),
+    /// Generates '_bind_methods' from annotations instead of writing the bindings by hand.
+    /// Declarations inside a class are annotated with a comment above them: '// @gd.method', '// @gd.property(hint=range, "0,10")',
+    /// '// @gd.signal' (or '// @gd.signal died(int cause)'), '// @gd.enum' or '// @gd.constant'.
+    /// The bindings of 'player.h' are written to 'player.gen.cpp' next to it, which the build files already compile.
+    /// 
+    /// Examples:
+    /// 
+    ///     'gdsetup bind -p path/to/project'                               - Generates the bindings of every annotated header
+    /// 
+    ///     'gdsetup bind player'                                           - Only generates 'player.gen.cpp'
+    Bind(BindArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AddCommands {
+    /// Creates another GDExtension module (same as 'gdsetup add NAME')
+    Module(AddModuleArgs),
+    /// Creates a new class inside of an existing module and registers it in its register_types.cpp
+    Class(AddClassArgs),
+    /// Adds a method to an existing class: the declaration, a stub and its binding inside '_bind_methods'
+    Method(AddMethodArgs),
+    /// Adds a property to an existing class: a member, its setter and getter and the 'ADD_PROPERTY' binding
+    Property(AddPropertyArgs),
+    /// Adds a signal with typed arguments to an existing class, optionally with a method that emits it
+    Signal(AddSignalArgs),
+    /// Adds an enum (or with --bitfield a bitfield) to an existing class and binds its values
+    Enum(AddEnumArgs),
+    /// Adds an integer constant to an existing class and binds it
+    Constant(AddConstantArgs),
 }
\ No newline at end of file
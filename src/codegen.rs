@@ -1,374 +1,262 @@
+use anyhow::{Result, Error};
+use minijinja::{context, value::merge_maps, Value};
 
-pub fn get_classname_uppercase(classname: &str) -> String {
-    let cln = classname.split_at(1);
-    let mut class_name_first_upper = cln.0.to_uppercase();
-    class_name_first_upper.push_str(cln.1);
-    class_name_first_upper
+use crate::{api::{ExtensionApi, VirtualMethod}, level::{self, InitLevel, Registrations}, naming, templates::Templates, version::GodotVersion};
+
+
+/// Common base classes of generated classes, any other engine class works as well
+pub const BASE_CLASSES: [&str; 7] = ["Node", "Node2D", "Node3D", "Control", "Resource", "Object", "RefCounted"];
+pub const DEFAULT_BASE_CLASS: &str = "RefCounted";
+
+
+/// Converts a Godot class name into the snake_case name godot-cpp uses for its headers (e.g. 'CharacterBody3D' -> 'character_body3d')
+pub fn get_godot_header_name(godot_class: &str) -> String {
+    naming::to_snake_case(godot_class)
 }
 
 
-pub fn generate_register_h(classname: &str) -> String {
-    format!("
-#ifndef {}_REGISTER_TYPES_H
-#define {}_REGISTER_TYPES_H
+/// Returns the include path of a Godot class
+pub fn get_godot_include(godot_class: &str) -> String {
+    match godot_class {
+        "Object" => "godot_cpp/core/object.hpp".to_string(),
+        _ => format!("godot_cpp/classes/{}.hpp", get_godot_header_name(godot_class)),
+    }
+}
 
-#include <godot_cpp/core/class_db.hpp>
 
-using namespace godot;
+/// Variables every template can use
+fn get_module_context(module_name: &str, version: GodotVersion) -> Value {
+    context! {
+        module_name => module_name,
+        module_name_upper => naming::to_screaming_snake_case(module_name),
+        entry_symbol => format!("{}_library_init", module_name),
+        godot_version => version,
+        godot_cpp_branch => version.godot_cpp_branch(),
+    }
+}
 
-// Note: It is not recommended to rename that function, except you know what you are doing
-void initialize_{}_module(ModuleInitializationLevel p_level);
-// Note: It is not recommended to rename that function, except you know what you are doing
-void uninitialize_{}_module(ModuleInitializationLevel p_level);
 
-#endif // {}_REGISTER_TYPES_H", classname.to_uppercase(), classname.to_uppercase(), classname, classname, classname.to_uppercase())
+/// Variables of the templates which generate a class
+fn get_class_context(classname: &str, base: &str, api: &ExtensionApi, version: GodotVersion) -> Value {
+    context! {
+        godot_version => version,
+        godot_cpp_branch => version.godot_cpp_branch(),
+        class_file => classname,
+        class_file_upper => naming::to_screaming_snake_case(classname),
+        class_name => naming::to_pascal_case(classname),
+        base_class => base,
+        base_include => get_godot_include(base),
+        is_node => api.inherits_from(base, "Node"),
+        is_resource => api.inherits_from(base, "Resource"),
+    }
 }
 
 
-pub fn generate_register_cpp(classname: &str) -> String {
-    format!("
-#include \"register_types.h\"
+/// Variables the files of a template pack can use
+pub fn get_pack_context(module_name: &str, classname: &str, base: &str, api: &ExtensionApi, godot_dir: &str, src_dir: &str, version: GodotVersion) -> Value {
+    merge_maps([
+        context! { godot_dir => godot_dir, src_dir => src_dir },
+        get_class_context(classname, base, api, version),
+        get_module_context(module_name, version),
+    ])
+}
+
 
-#include <gdextension_interface.h>
+pub fn generate_register_h(templates: &Templates, module_name: &str, version: GodotVersion) -> Result<String, Error> {
+    templates.render("register_types.h", get_module_context(module_name, version))
+}
 
-#include <godot_cpp/core/class_db.hpp>
-#include <godot_cpp/core/defs.hpp>
-#include <godot_cpp/godot.hpp>
 
-#include \"{}.h\"
-//#include \"tests.h\"
+/// Parameters of the library entry point, they changed with Godot 4.1
+pub fn get_entry_point_params(version: GodotVersion) -> (&'static str, &'static str) {
+    if version.uses_get_proc_address() {
+        ("GDExtensionInterfaceGetProcAddress p_get_proc_address, const GDExtensionClassLibraryPtr p_library, GDExtensionInitialization *r_initialization", "p_get_proc_address, p_library, r_initialization")
+    } else {
+        ("const GDExtensionInterface *p_interface, GDExtensionClassLibraryPtr p_library, GDExtensionInitialization *r_initialization", "p_interface, p_library, r_initialization")
+    }
+}
+
+
+/// The module 'module_name' registers the class 'classname' at 'level', every other identifier comes from the module
+pub fn generate_register_cpp(templates: &Templates, module_name: &str, classname: &str, base: &str, level: InitLevel, api: &ExtensionApi, version: GodotVersion) -> Result<String, Error> {
+    let (params, args) = get_entry_point_params(version);
+    let class_name = naming::to_pascal_case(classname);
+    let plugin = level::get_plugin_registration(&class_name, base, version);
+    let mut registrations = Registrations::new();
+    registrations.insert(level, [Some(format!("ClassDB::register_class<{}>();", class_name)), plugin.clone()].into_iter().flatten().collect());
+    templates.render("register_types.cpp", merge_maps([
+        context! {
+            entry_point_params => params,
+            entry_point_args => args,
+            registrations => level::format_registrations(&registrations),
+            minimum_level => level::get_minimum_level(&registrations).constant(),
+            is_editor_plugin => plugin.is_some(),
+        },
+        get_class_context(classname, base, api, version),
+        get_module_context(module_name, version),
+    ]))
+}
 
-using namespace godot;
 
-// Note: It is not recommended to rename that function, except you know what you are doing
-void initialize_{}_module(ModuleInitializationLevel p_level) {{
-    if (p_level != MODULE_INITIALIZATION_LEVEL_SCENE) {{
-        return;
-    }}
+/// Variables of the overridden virtual methods, they replace the example overrides of the templates
+fn get_overrides_context(base: &str, overrides: &[VirtualMethod]) -> Value {
+    let base_include = get_godot_include(base);
+    let mut includes = overrides.iter()
+        .flat_map(|method| method.includes.iter().cloned())
+        .filter(|include| *include != base_include)
+        .collect::<Vec<String>>();
+    includes.sort();
+    includes.dedup();
+    context! {
+        overrides => Value::from_serialize(overrides),
+        override_includes => includes,
+    }
+}
 
-    ClassDB::register_class<{}>();
-}}
 
-// Note: It is not recommended to rename that function, except you know what you are doing
-void uninitialize_{}_module(ModuleInitializationLevel p_level) {{
-    if (p_level != MODULE_INITIALIZATION_LEVEL_SCENE) {{
-        return;
-    }}
-}}
+pub fn generate_class_cpp(templates: &Templates, classname: &str, base: &str, overrides: &[VirtualMethod], api: &ExtensionApi, version: GodotVersion) -> Result<String, Error> {
+    templates.render("class.cpp", merge_maps([
+        get_overrides_context(base, overrides),
+        get_class_context(classname, base, api, version),
+    ]))
+}
 
-extern \"C\" {{
-// Initialization.
-// Note: It is not recommended to rename that function, except you know what you are doing
-GDExtensionBool GDE_EXPORT {}_library_init(const GDExtensionInterface *p_interface, GDExtensionClassLibraryPtr p_library, GDExtensionInitialization *r_initialization) {{
-    godot::GDExtensionBinding::InitObject init_obj(p_interface, p_library, r_initialization);
 
-    init_obj.register_initializer(initialize_{}_module);
-    init_obj.register_terminator(uninitialize_{}_module);
-    init_obj.set_minimum_library_initialization_level(MODULE_INITIALIZATION_LEVEL_SCENE);
+pub fn generate_class_h(templates: &Templates, classname: &str, base: &str, overrides: &[VirtualMethod], api: &ExtensionApi, version: GodotVersion) -> Result<String, Error> {
+    templates.render("class.h", merge_maps([
+        get_overrides_context(base, overrides),
+        get_class_context(classname, base, api, version),
+    ]))
+}
 
-    return init_obj.init();
-}}
-}}
 
-    ", classname, classname, get_classname_uppercase(classname), classname, classname, classname, classname)
+/// The '_bind_methods' of the annotated classes of 'header', see 'gdsetup bind'
+pub fn generate_class_gen_cpp(templates: &Templates, header: &str, classes: Vec<Value>, version: GodotVersion) -> Result<String, Error> {
+    templates.render("class.gen.cpp", context! {
+        header => header,
+        classes => classes,
+        godot_version => version,
+        godot_cpp_branch => version.godot_cpp_branch(),
+    })
 }
 
 
-pub fn generate_class_cpp(classname: &str) -> String {
-    let class_name_first_upper = get_classname_uppercase(classname);
+/// Compiles the class reference into the library, see: https://docs.godotengine.org/en/stable/tutorials/scripting/gdextension/gdextension_docs_system.html
+pub fn get_sconstruct_doc_data(src_dir: &str) -> String {
     format!("
-#include \"{}.h\"
-
-#include <godot_cpp/core/class_db.hpp>
-
-#include <godot_cpp/classes/global_constants.hpp>
-#include <godot_cpp/variant/utility_functions.hpp>
-
-using namespace godot;
-{}::{}() {{
-}}
-{}::~{}() {{
-}}
-void {}::exampleFunction(int number) {{
-    UtilityFunctions::print(\"Your number was: \", number);
-}}
-
-void {}::_bind_methods() {{
-    ClassDB::bind_method(D_METHOD(\"exampleFunction\", \"number\"), &{}::exampleFunction);
-}}"
-, classname, class_name_first_upper, class_name_first_upper, class_name_first_upper, class_name_first_upper, class_name_first_upper, class_name_first_upper, class_name_first_upper)
+if env[\"target\"] in [\"editor\", \"template_debug\"]:
+    doc_data = env.GodotCPPDocData(\"{}/gen/doc_data.gen.cpp\", source=Glob(\"doc_classes/*.xml\"))
+    sources.append(doc_data)
+", src_dir)
 }
 
 
-pub fn generate_class_h(classname: &str) -> String {
-    let class_name_first_upper = get_classname_uppercase(classname);
-    format!(
-"
-#ifndef {}_CLASS_H
-#define {}_CLASS_H
-
-// We don't need windows.h in this example plugin but many others do, and it can
-// lead to annoying situations due to the ton of macros it defines.
-// So we include it and make sure CI warns us if we use something that conflicts
-// with a Windows define.
-#ifdef WIN32
-#include <windows.h>
-#endif
+pub fn generate_sconstruct(templates: &Templates, module_name: &str, godot_dir: &str, src_dir: &str, version: GodotVersion) -> Result<String, Error> {
+    let doc_data = if version.has_doc_data() { get_sconstruct_doc_data(src_dir) } else { String::new() };
+    templates.render("SConstruct", context! {
+        doc_data => doc_data,
+        godot_dir => godot_dir,
+        src_dir => src_dir,
+        ..get_module_context(module_name, version),
+    })
+}
 
-#include <godot_cpp/classes/global_constants.hpp>
-#include <godot_cpp/variant/utility_functions.hpp>
-#include <godot_cpp/classes/ref_counted.hpp>
 
-#include <godot_cpp/core/binder_common.hpp>
+pub fn generate_sconstruct_module(templates: &Templates, module_name: &str, godot_dir: &str, src_dir: &str, version: GodotVersion) -> Result<String, Error> {
+    templates.render("SConstruct.module", context! {
+        godot_dir => godot_dir,
+        src_dir => src_dir,
+        ..get_module_context(module_name, version),
+    })
+}
 
-using namespace godot;
 
-class {} : public RefCounted {{
-    GDCLASS({}, RefCounted);
+pub fn generate_cmakelists(templates: &Templates, module_name: &str, src_dir: &str, version: GodotVersion) -> Result<String, Error> {
+    templates.render("CMakeLists.txt", context! {
+        src_dir => src_dir,
+        ..get_module_context(module_name, version),
+    })
+}
 
-private:
 
-protected:
-    static void _bind_methods();
+pub fn generate_cmakelists_module(templates: &Templates, module_name: &str, src_dir: &str, version: GodotVersion) -> Result<String, Error> {
+    templates.render("CMakeLists.module.txt", context! {
+        src_dir => src_dir,
+        ..get_module_context(module_name, version),
+    })
+}
 
-public:
-    {}();
-    ~{}();
 
-    void exampleFunction(int number);
-}};
-#endif // {}_CLASS_H
-", classname.to_uppercase(), classname.to_uppercase(), class_name_first_upper, class_name_first_upper, class_name_first_upper, class_name_first_upper, classname.to_uppercase())
+/// Lines of the [configuration] section of a .gdextension file which depend on the Godot version
+pub fn get_gdextension_configuration(version: GodotVersion) -> String {
+    let mut configuration = String::new();
+    if let Some(minimum) = version.compatibility_minimum() {
+        configuration.push_str(&format!("compatibility_minimum = \"{}\"\n", minimum));
+    };
+    if version.is_reloadable() {
+        configuration.push_str("reloadable = true\n");
+    };
+    configuration
 }
 
 
-pub fn generate_sconstruct(classname: &str) -> String {
-    format!("
-# See: https://github.com/godotengine/godot-cpp/blob/master/test/SConstruct
-
-#!/usr/bin/env python
-import os
-import sys
-
-env = SConscript(\"godot-cpp/SConstruct\")
-
-# For the reference:
-# - CCFLAGS are compilation flags shared between C and C++
-# - CFLAGS are for C-specific compilation flags
-# - CXXFLAGS are for C++-specific compilation flags
-# - CPPFLAGS are for pre-processor flags
-# - CPPDEFINES are for pre-processor defines
-# - LINKFLAGS are for linking flags
-
-# tweak this if you want to use different folders, or more folders, to store your source code in.
-env.Append(CPPPATH=[\"src/\"])
-sources = Glob(\"src/*.cpp\")
-
-if env[\"platform\"] == \"macos\":
-    library = env.SharedLibrary(
-        \"godot/bin/libgd{}.{{}}.{{}}.framework/libgd{}.{{}}.{{}}\".format(
-            env[\"platform\"], env[\"target\"], env[\"platform\"], env[\"target\"]
-        ),
-        source=sources,
-    )
-else:
-    library = env.SharedLibrary(
-        \"godot/bin/libgd{}{{}}{{}}\".format(env[\"suffix\"], env[\"SHLIBSUFFIX\"]),
-        source=sources,
-    )
-
-Default(library)
-    ", classname, classname, classname)
+pub fn generate_gdextension(templates: &Templates, module_name: &str, version: GodotVersion) -> Result<String, Error> {
+    templates.render("module.gdextension", context! {
+        compatibility_minimum => version.compatibility_minimum(),
+        reloadable => version.is_reloadable(),
+        ..get_module_context(module_name, version),
+    })
 }
 
 
-pub fn generate_cmakelists(classname: &str) -> String {
-    format!("
-# See: https://github.com/godotengine/godot-cpp/blob/master/test/CMakeLists.txt
-
-project({})
-cmake_minimum_required(VERSION 3.6)
-
-set(GODOT_GDEXTENSION_DIR ../gdextension/ CACHE STRING \"Path to GDExtension interface header directory\")
-set(CPP_BINDINGS_PATH ../ CACHE STRING \"Path to C++ bindings\")
-
-if(CMAKE_SYSTEM_NAME STREQUAL \"Linux\")
-    set(TARGET_PATH x11)
-elseif(CMAKE_SYSTEM_NAME STREQUAL \"Windows\")
-    set(TARGET_PATH win64)
-elseif(CMAKE_SYSTEM_NAME STREQUAL \"Darwin\")
-    set(TARGET_PATH macos)
-else()
-    message(FATAL_ERROR \"Not implemented support for ${{CMAKE_SYSTEM_NAME}}\")
-endif()
-
-# Change the output directory to the bin directory
-set(BUILD_PATH ${{CMAKE_SOURCE_DIR}}/bin/${{TARGET_PATH}})
-set(CMAKE_ARCHIVE_OUTPUT_DIRECTORY \"${{BUILD_PATH}}\")
-set(CMAKE_LIBRARY_OUTPUT_DIRECTORY \"${{BUILD_PATH}}\")
-set(CMAKE_RUNTIME_OUTPUT_DIRECTORY \"${{BUILD_PATH}}\")
-SET(CMAKE_RUNTIME_OUTPUT_DIRECTORY_DEBUG \"${{BUILD_PATH}}\")
-SET(CMAKE_RUNTIME_OUTPUT_DIRECTORY_RELEASE \"${{BUILD_PATH}}\")
-SET(CMAKE_LIBRARY_OUTPUT_DIRECTORY_DEBUG \"${{BUILD_PATH}}\")
-SET(CMAKE_LIBRARY_OUTPUT_DIRECTORY_RELEASE \"${{BUILD_PATH}}\")
-SET(CMAKE_ARCHIVE_OUTPUT_DIRECTORY_DEBUG \"${{BUILD_PATH}}\")
-SET(CMAKE_ARCHIVE_OUTPUT_DIRECTORY_RELEASE \"${{BUILD_PATH}}\")
-
-# Set the c++ standard to c++17
-set(CMAKE_CXX_STANDARD 17)
-set(CMAKE_CXX_STANDARD_REQUIRED ON)
-set(CMAKE_CXX_EXTENSIONS OFF)
-
-set(GODOT_COMPILE_FLAGS )
-set(GODOT_LINKER_FLAGS )
-
-if (\"${{CMAKE_CXX_COMPILER_ID}}\" STREQUAL \"MSVC\")
-    # using Visual Studio C++
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} /EHsc /WX\") # /GF /MP
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} /DTYPED_METHOD_BIND\")
-
-    if(CMAKE_BUILD_TYPE MATCHES Debug)
-        set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} /MDd\") # /Od /RTC1 /Zi
-    else()
-        set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} /MD /O2\") # /Oy /GL /Gy
-        STRING(REGEX REPLACE \"/RTC(su|[1su])\" \"\" CMAKE_CXX_FLAGS \"${{CMAKE_CXX_FLAGS}}\")
-        string(REPLACE \"/RTC1\" \"\" CMAKE_CXX_FLAGS_DEBUG ${{CMAKE_CXX_FLAGS_DEBUG}})
-    endif(CMAKE_BUILD_TYPE MATCHES Debug)
-
-    # Disable conversion warning, truncation, unreferenced var, signed mismatch
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} /wd4244 /wd4305 /wd4101 /wd4018 /wd4267\")
-
-    add_definitions(-DNOMINMAX)
-
-    # Unkomment for warning level 4
-    #if(CMAKE_CXX_FLAGS MATCHES \"/W[0-4]\")
-    #	string(REGEX REPLACE \"/W[0-4]\" \"\" CMAKE_CXX_FLAGS \"${{CMAKE_CXX_FLAGS}}\")
-    #endif()
-
-else()
-
-#elseif (\"${{CMAKE_CXX_COMPILER_ID}}\" STREQUAL \"Clang\")
-    # using Clang
-#elseif (\"${{CMAKE_CXX_COMPILER_ID}}\" STREQUAL \"GNU\")
-    # using GCC and maybe MinGW?
-
-    set(GODOT_LINKER_FLAGS \"-static-libgcc -static-libstdc++ -Wl,-R,\"$$ORIGIN\"\")
-
-    # Hmm.. maybe to strikt?
-    set(GODOT_COMPILE_FLAGS \"-fPIC -g -Wwrite-strings\")
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wchar-subscripts -Wcomment -Wdisabled-optimization\")
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wformat -Wformat=2 -Wformat-security -Wformat-y2k\")
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wimport -Winit-self -Winline -Winvalid-pch -Werror\")
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wmissing-braces -Wmissing-format-attribute\")
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wmissing-include-dirs -Wmissing-noreturn -Wpacked -Wpointer-arith\")
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wredundant-decls -Wreturn-type -Wsequence-point\")
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wswitch -Wswitch-enum -Wtrigraphs\")
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wuninitialized -Wunknown-pragmas -Wunreachable-code -Wunused-label\")
-    set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wunused-value -Wvariadic-macros -Wvolatile-register-var -Wno-error=attributes\")
-
-    # -Wshadow -Wextra -Wall -Weffc++ -Wfloat-equal -Wstack-protector -Wunused-parameter -Wsign-compare -Wunused-variable -Wcast-align
-    # -Wunused-function -Wstrict-aliasing -Wstrict-aliasing=2 -Wmissing-field-initializers
-
-    if(NOT CMAKE_SYSTEM_NAME STREQUAL \"Android\")
-        set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -Wno-ignored-attributes\")
-    endif()
-
-    if(CMAKE_BUILD_TYPE MATCHES Debug)
-        set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -fno-omit-frame-pointer -O0\")
-    else()
-        set(GODOT_COMPILE_FLAGS \"${{GODOT_COMPILE_FLAGS}} -O3\")
-    endif(CMAKE_BUILD_TYPE MATCHES Debug)
-endif()
-
-# Get Sources
-file(GLOB_RECURSE SOURCES src/*.c**)
-file(GLOB_RECURSE HEADERS include/*.h**)
-
-# Define our godot-cpp library
-add_library(${{PROJECT_NAME}} SHARED ${{SOURCES}} ${{HEADERS}})
-
-target_include_directories(${{PROJECT_NAME}} SYSTEM
-    PRIVATE
-        ${{CPP_BINDINGS_PATH}}/include
-        ${{CPP_BINDINGS_PATH}}/gen/include
-        ${{GODOT_GDEXTENSION_DIR}}
-)
-
-# Create the correct name (godot.os.build_type.system_bits)
-# Synchronized with godot-cpp's CMakeLists.txt
-
-set(BITS 32)
-if(CMAKE_SIZEOF_VOID_P EQUAL 8)
-    set(BITS 64)
-endif(CMAKE_SIZEOF_VOID_P EQUAL 8)
-
-if(CMAKE_BUILD_TYPE MATCHES Debug)
-    set(GODOT_CPP_BUILD_TYPE Debug)
-else()
-    set(GODOT_CPP_BUILD_TYPE Release)
-endif()
-
-string(TOLOWER ${{CMAKE_SYSTEM_NAME}} SYSTEM_NAME)
-string(TOLOWER ${{GODOT_CPP_BUILD_TYPE}} BUILD_TYPE)
-
-if(ANDROID)
-    # Added the android abi after system name
-    set(SYSTEM_NAME ${{SYSTEM_NAME}}.${{ANDROID_ABI}})
-endif()
-
-if(CMAKE_VERSION VERSION_GREATER \"3.13\")
-    target_link_directories(${{PROJECT_NAME}}
-        PRIVATE
-        ${{CPP_BINDINGS_PATH}}/bin/
-    )
-
-    target_link_libraries(${{PROJECT_NAME}}
-        godot-cpp.${{SYSTEM_NAME}}.${{BUILD_TYPE}}$<$<NOT:$<PLATFORM_ID:Android>>:.${{BITS}}>
-    )
-else()
-    target_link_libraries(${{PROJECT_NAME}}
-            ${{CPP_BINDINGS_PATH}}/bin/libgodot-cpp.${{SYSTEM_NAME}}.${{BUILD_TYPE}}$<$<NOT:$<PLATFORM_ID:Android>>:.${{BITS}}>.a
-    )
-endif()
-
-# Add the compile flags
-set_property(TARGET ${{PROJECT_NAME}} APPEND_STRING PROPERTY COMPILE_FLAGS ${{GODOT_COMPILE_FLAGS}})
-set_property(TARGET ${{PROJECT_NAME}} APPEND_STRING PROPERTY LINK_FLAGS ${{GODOT_LINKER_FLAGS}})
-
-set_property(TARGET ${{PROJECT_NAME}} PROPERTY OUTPUT_NAME \"{}\")
-    ", classname, classname)
+pub fn generate_gdextension_list(templates: &Templates, module_name: &str, version: GodotVersion) -> Result<String, Error> {
+    templates.render("extension_list.cfg", get_module_context(module_name, version))
 }
 
 
-pub fn generate_gdextension(classname: &str) -> String {
-    format!("
-[configuration]
-entry_symbol = \"{}_library_init\"
-
-[libraries]
-
-macos.debug = \"res://bin/libgd{}.macos.template_debug.framework\"
-macos.release = \"res://bin/libgd{}.macos.template_release.framework\"
-windows.debug.x86_32 = \"res://bin/libgd{}.windows.template_debug.x86_32.dll\"
-windows.release.x86_32 = \"res://bin/libgd{}.windows.template_release.x86_32.dll\"
-windows.debug.x86_64 = \"res://bin/libgd{}.windows.template_debug.x86_64.dll\"
-windows.release.x86_64 = \"res://bin/libgd{}.windows.template_release.x86_64.dll\"
-linux.debug.x86_64 = \"res://bin/libgd{}.linux.template_debug.x86_64.so\"
-linux.release.x86_64 = \"res://bin/libgd{}.linux.template_release.x86_64.so\"
-linux.debug.arm64 = \"res://bin/libgd{}.linux.template_debug.arm64.so\"
-linux.release.arm64 = \"res://bin/libgd{}.linux.template_release.arm64.so\"
-linux.debug.rv64 = \"res://bin/libgd{}.linux.template_debug.rv64.so\"
-linux.release.rv64 = \"res://bin/libgd{}.linux.template_release.rv64.so\"
-android.debug.x86_64 = \"res://bin/libgd{}.android.template_debug.x86_64.so\"
-android.release.x86_64 = \"res://bin/libgd{}.android.template_release.x86_64.so\"
-android.debug.arm64 = \"res://bin/libgd{}.android.template_debug.arm64.so\"
-android.release.arm64 = \"res://bin/libgd{}.android.template_release.arm64.so\"
-    ", classname, classname, classname, classname, classname, classname, classname, classname, classname, classname, classname, classname, classname, classname, classname, classname, classname)
+/// Adds a line to the contents of a .gitignore (which might not exist yet), unless it is already there
+pub fn add_to_gitignore(gitignore: &str, entry: &str) -> String {
+    if gitignore.lines().any(|line| line.trim() == entry) {
+        return gitignore.to_string();
+    };
+    let mut gitignore = gitignore.trim_end().to_string();
+    if !gitignore.is_empty() {
+        gitignore.push('\n');
+    };
+    gitignore.push_str(entry);
+    gitignore.push('\n');
+    gitignore
 }
 
 
-pub fn generate_gdextension_list(classname: &str) -> String {
-    format!("
-res://{}.gdextension
-    ", classname)
-}
\ No newline at end of file
+/// Adds the .gdextension file of a module to the contents of an existing extension_list.cfg
+pub fn add_to_gdextension_list(extension_list: &str, module_name: &str) -> String {
+    let entry = format!("res://{}.gdextension", module_name);
+    let mut extension_list = extension_list.trim_end().to_string();
+    if !extension_list.lines().any(|line| line.trim() == entry) {
+        extension_list.push('\n');
+        extension_list.push_str(&entry);
+    };
+    extension_list.push('\n');
+    extension_list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_stub_follows_the_inheritance() {
+        let templates = Templates::builtin();
+        let api = ExtensionApi::bundled();
+        let version = crate::version::DEFAULT_VERSION.parse::<GodotVersion>().unwrap();
+        for base in ["Resource", "Texture2D", "Material"] {
+            let header = generate_class_h(&templates, "item", base, &[], &api, version).unwrap();
+            assert!(header.contains("void _get_property_list(List<PropertyInfo> *p_list) const;"), "{}", base);
+            assert!(generate_class_cpp(&templates, "item", base, &[], &api, version).unwrap().contains("::_get_property_list("));
+        };
+        let header = generate_class_h(&templates, "player", "Node3D", &[], &api, version).unwrap();
+        assert!(header.contains("void _ready() override;") && !header.contains("_get_property_list"));
+        assert!(!generate_class_h(&templates, "data", "RefCounted", &[], &api, version).unwrap().contains("_get_property_list"));
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::{Result, Error, Context};
+
+use crate::naming;
+
+
+/// A parameter of a method signature, e.g. 'float speed = 1.0'
+pub struct Param {
+    pub param_type: String,
+    pub name: String,
+    pub default: Option<String>,
+}
+
+/// A method signature like 'set_speed(float speed)' or 'get_health() const -> int'
+pub struct Signature {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub return_type: String,
+    pub is_const: bool,
+    pub is_static: bool,
+}
+
+/// A class declared inside a header, 'start' is the line of 'class Name ...' and 'end' the one of its closing '};'
+pub struct ClassDeclaration {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+
+/// Returns the class name of the first 'GDCLASS(Name, Base)' of a header
+pub fn get_gdclass_name(header: &str) -> Option<String> {
+    let start = header.find("GDCLASS(")? + "GDCLASS(".len();
+    let (name, _) = header[start..].split_once(',')?;
+    Some(name.trim().to_string())
+}
+
+
+/// Returns the top-level class declarations of a header. Forward declarations and nested classes are skipped
+pub fn find_class_declarations(lines: &[String]) -> Vec<ClassDeclaration> {
+    let mut classes = vec![];
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx].trim();
+        let name = line.strip_prefix("class ")
+            .filter(|_| !line.ends_with(';'))
+            .and_then(|rest| rest.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').next())
+            .filter(|name| !name.is_empty());
+        match name.and_then(|name| find_class_end(lines, idx).map(|end| (name, end))) {
+            Some((name, end)) => {
+                classes.push(ClassDeclaration { name: name.to_string(), start: idx, end });
+                idx = end + 1;
+            },
+            None => idx += 1,
+        };
+    };
+    classes
+}
+
+
+/// Returns the line which closes the class declaration starting at 'start', nested enums and structs are skipped
+pub fn find_class_end(lines: &[String], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, line) in lines.iter().enumerate().skip(start) {
+        for c in line.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => (),
+            };
+        };
+        if depth == 0 && line.trim_start().starts_with("};") {
+            return Some(idx);
+        };
+    };
+    None
+}
+
+
+/// Whether the line starts the declaration 'class Name : public Base {'
+pub fn is_class_declaration(line: &str, classname: &str) -> bool {
+    line.trim_start().strip_prefix("class ")
+        .map(|rest| rest.split(|c: char| !c.is_ascii_alphanumeric() && c != '_').next() == Some(classname))
+        .unwrap_or(false)
+}
+
+
+/// Parses the C++ declaration of a method, e.g. 'static Vector2 get_speed(float p_factor = 1.0) const;'.
+/// Inline bodies and 'virtual', 'override' or 'final' are ignored
+pub fn parse_method_declaration(declaration: &str) -> Result<Signature, Error> {
+    let declaration = declaration.trim().trim_end_matches(';').trim();
+    let open = declaration.find('(').with_context(|| format!("'{}' is not a method declaration.", declaration))?;
+    let close = find_closing_parenthesis(declaration, open).with_context(|| format!("'{}' is missing a closing ')'.", declaration))?;
+    let (left, params, suffix) = (&declaration[..open], &declaration[open + 1..close], &declaration[close + 1..]);
+
+    let mut is_static = false;
+    let mut words = vec![];
+    for word in left.split_whitespace() {
+        match word {
+            "static" => is_static = true,
+            "virtual" | "inline" | "_FORCE_INLINE_" => (),
+            _ => words.push(word),
+        };
+    };
+    let left = words.join(" ");
+    let name_start = left.rfind(|c: char| !c.is_ascii_alphanumeric() && c != '_').map(|idx| idx + 1).unwrap_or(0);
+    let (return_type, name) = left.split_at(name_start);
+    if return_type.trim().is_empty() {
+        return Err(Error::msg(format!("'{}' has no return type, constructors can't be bound.", declaration)));
+    };
+    naming::validate_identifier(name, "method")?;
+    let suffix = suffix.split('{').next().unwrap_or_default();
+    let is_const = suffix.split_whitespace().any(|word| word == "const");
+
+    let mut parsed = vec![];
+    for param in split_params(params) {
+        parsed.push(parse_param(&param)?);
+    };
+    Ok(Signature { name: name.to_string(), params: parsed, return_type: return_type.trim().to_string(), is_const, is_static })
+}
+
+
+/// Parses the declaration of a member variable, e.g. 'float speed = 1.0;' or 'int health{100};'.
+/// The initial value becomes the default of the returned parameter
+pub fn parse_member_declaration(declaration: &str) -> Result<Param, Error> {
+    let declaration = declaration.trim().trim_end_matches(';').trim();
+    if declaration.contains('(') && !declaration.contains('=') {
+        return Err(Error::msg(format!("'{}' is not a member variable.", declaration)));
+    };
+    match declaration.split_once('{') {
+        Some((member, initializer)) if !member.contains('=') => {
+            let mut param = parse_param(member)?;
+            param.default = Some(initializer.trim_end_matches('}').trim().to_string()).filter(|d| !d.is_empty());
+            Ok(param)
+        },
+        _ => parse_param(declaration),
+    }
+}
+
+
+/// Returns the index of the ')' closing the '(' at 'open'
+pub fn find_closing_parenthesis(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    for (idx, c) in text.char_indices().skip_while(|(idx, _)| *idx < open) {
+        match c {
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                };
+            },
+            _ => (),
+        };
+    };
+    None
+}
+
+
+/// Splits a parameter list at the commas which are not inside of '<>', '()' or a string
+pub fn split_params(params: &str) -> Vec<String> {
+    let mut split = vec![];
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    for c in params.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            '<' | '(' if !in_string => depth += 1,
+            '>' | ')' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                split.push(std::mem::take(&mut current));
+                continue;
+            },
+            _ => (),
+        };
+        current.push(c);
+    };
+    split.push(current);
+    split.into_iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect()
+}
+
+
+/// Parses a parameter like 'float speed', 'const String &name' or 'int count = 1'
+pub fn parse_param(param: &str) -> Result<Param, Error> {
+    let (declaration, default) = match param.split_once('=') {
+        Some((declaration, default)) => (declaration.trim(), Some(default.trim().to_string())),
+        None => (param.trim(), None),
+    };
+    let name_start = declaration.rfind(|c: char| !c.is_ascii_alphanumeric() && c != '_').map(|idx| idx + 1).unwrap_or(0);
+    let (param_type, name) = declaration.split_at(name_start);
+    if param_type.trim().is_empty() || name.is_empty() {
+        return Err(Error::msg(format!("'{}' needs a type and a name, e.g. 'float speed'.", param)));
+    };
+    naming::validate_identifier(name, "parameter")?;
+    Ok(Param { param_type: param_type.trim().to_string(), name: name.to_string(), default })
+}
+
+
+pub fn format_param(param: &Param, with_default: bool) -> String {
+    let mut formatted = if param.param_type.ends_with('&') || param.param_type.ends_with('*') {
+        format!("{}{}", param.param_type, param.name)
+    } else {
+        format!("{} {}", param.param_type, param.name)
+    };
+    if let (true, Some(default)) = (with_default, &param.default) {
+        formatted.push_str(&format!(" = {}", default));
+    };
+    formatted
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_declarations_keep_qualifiers() {
+        let signature = parse_method_declaration("static Vector2 get_speed(float p_factor = 1.0) const;").unwrap();
+        assert_eq!(signature.name, "get_speed");
+        assert_eq!(signature.return_type, "Vector2");
+        assert!(signature.is_static && signature.is_const);
+        assert_eq!(signature.params.len(), 1);
+        assert_eq!(signature.params[0].default.as_deref(), Some("1.0"));
+
+        let signature = parse_method_declaration("virtual Ref<Resource> load(const String &p_path) override;").unwrap();
+        assert_eq!(signature.return_type, "Ref<Resource>");
+        assert!(!signature.is_static && !signature.is_const);
+        assert_eq!(format_param(&signature.params[0], true), "const String &p_path");
+    }
+
+    #[test]
+    fn method_declarations_with_templates_macros_and_bodies() {
+        let signature = parse_method_declaration("_FORCE_INLINE_ TypedArray<Node> find(const HashMap<String, Vector<int>> &p_map, int p_depth = MAX(1, 2)) const { return {}; }").unwrap();
+        assert_eq!(signature.name, "find");
+        assert_eq!(signature.return_type, "TypedArray<Node>");
+        assert!(signature.is_const);
+        assert_eq!(signature.params.len(), 2);
+        assert_eq!(signature.params[0].param_type, "const HashMap<String, Vector<int>> &");
+        assert_eq!(signature.params[1].default.as_deref(), Some("MAX(1, 2)"));
+
+        let signature = parse_method_declaration("String describe(const String &p_text = \"a, (b\");").unwrap();
+        assert_eq!(signature.params.len(), 1);
+        assert_eq!(signature.params[0].default.as_deref(), Some("\"a, (b\""));
+    }
+
+    #[test]
+    fn invalid_method_declarations() {
+        assert!(parse_method_declaration("Player();").is_err());
+        assert!(parse_method_declaration("int health;").is_err());
+        assert!(parse_method_declaration("void broken(int p_value;").is_err());
+        assert!(parse_method_declaration("void bad(int);").is_err());
+    }
+
+    #[test]
+    fn member_declarations() {
+        let member = parse_member_declaration("float speed = 1.0;").unwrap();
+        assert_eq!((member.param_type.as_str(), member.name.as_str(), member.default.as_deref()), ("float", "speed", Some("1.0")));
+        let member = parse_member_declaration("int health{100};").unwrap();
+        assert_eq!(member.default.as_deref(), Some("100"));
+        assert!(parse_member_declaration("void tick();").is_err());
+    }
+}
@@ -1,10 +1,52 @@
-use std::process::Command;
+use std::path::{Component, Path};
 
-use crate::{cli::*, codegen, print_output, get_basecommand};
+use minijinja::{context, value::merge_maps, Value};
+
+use crate::{cli::*, api::ExtensionApi, codegen, journal, level::InitLevel, naming, print_output, manifest::{Manifest, Module, Class, MANIFEST_FILE}, pack::TemplatePack, templates::Templates, version::GodotVersion, workspace::Workspace};
 use anyhow::{Result, Error, Context, Ok};
 use log::{info, debug, warn};
 
-pub fn init(pathargs: InitArgs, projectname: Option<String>, classname: &str, godot_dir: &str, src_dir: &str) -> Result<(), Error> {
+
+/// Files and folders of version control systems, these stay at the root when migrating a project into 'godot/'
+const VCS_METADATA: [&str; 8] = [".git", ".gitignore", ".gitattributes", ".gitmodules", ".hg", ".hgignore", ".svn", ".bzr"];
+
+
+/// Moves a file, folder or symlink. If 'to' is an existing folder, the contents of 'from' are merged into it.
+fn move_recursive(ws: &mut Workspace, from: &Path, to: &Path) -> Result<(), Error> {
+    if !ws.exists(to) {
+        info!("Moving `{}` into `{}` ...", from.display(), to.display());
+        return ws.rename(from, to);
+    };
+    let is_folder = |p: &Path| ws.is_dir(p) && !ws.is_symlink(p);
+    if is_folder(from) && is_folder(to) {
+        for entry in ws.read_dir(from)? {
+            move_recursive(ws, &entry, &to.join(entry.file_name().unwrap()))?;
+        };
+        return ws.remove_dir(from);
+    };
+    Err(Error::msg(format!("Can't move '{}' to '{}', it already exists.", from.display(), to.display())))
+}
+
+
+/// The godot and source folders have to be different folders inside the project
+fn check_layout(godot_dir: &str, src_dir: &str) -> Result<(), Error> {
+    for dir in [godot_dir, src_dir] {
+        let path = Path::new(dir);
+        if dir.trim().is_empty() || path.is_absolute() || path.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(Error::msg(format!("'{}' is not a folder inside the project, use a relative path like 'godot' or 'native/src'.", dir)));
+        };
+        if dir == "godot-cpp" {
+            return Err(Error::msg("'godot-cpp' is the folder of the godot-cpp submodule."));
+        };
+    };
+    if Path::new(godot_dir) == Path::new(src_dir) {
+        return Err(Error::msg(format!("The Godot project and the sources can't both live inside '{}'.", godot_dir)));
+    };
+    Ok(())
+}
+
+
+pub fn init(ws: &mut Workspace, pathargs: InitArgs, projectname: Option<String>, classname: &str, godot_dir: &str, src_dir: &str) -> Result<(), Error> {
     // Decide whether to use the shorthand path version if provided
     let p = {
         if pathargs.path.is_some() {
@@ -22,107 +64,171 @@ pub fn init(pathargs: InitArgs, projectname: Option<String>, classname: &str, go
         return Err(Error::msg("Missing either a --path (-p) to an exisiting folder or a name for a new one."))
     };
 
-    let git_exists = Command::new("git").output().with_context(|| "Tried to find git").is_ok();
-    debug!("Testing whether the 'git' command exists: {}", git_exists);
+    let version = pathargs.godot_version.parse::<GodotVersion>()?;
+
+    // Get the template pack and its variables before anything is created
+    let pack = pathargs.template.as_deref().map(TemplatePack::fetch).transpose()?;
+    let variables = match &pack {
+        Some(pack) => pack.get_variables(&pathargs.vars)?,
+        None => Default::default(),
+    };
+    // The folder layout can be given on the command line or come from the template pack
+    let pack_info = pack.as_ref().map(|pack| &pack.manifest.template);
+    let godot_dir = pathargs.godot_dir.as_deref().or(pack_info.and_then(|info| info.godot_dir.as_deref())).unwrap_or(godot_dir);
+    let src_dir = pathargs.src_dir.as_deref().or(pack_info.and_then(|info| info.src_dir.as_deref())).unwrap_or(src_dir);
+    check_layout(godot_dir, src_dir)?;
+    let module_name = pathargs.module.as_deref().unwrap_or(classname);
+    let classname = pathargs.class.as_deref().unwrap_or(module_name);
+    naming::validate_module_name(module_name)?;
+    naming::validate_class_name(classname)?;
 
     let current_dir = std::env::current_dir().with_context(|| "Getting current directory.").unwrap_or_default();
+    let is_new_project = projectname.is_some();
+    let path = match (projectname, p) {
+        (Some(name), _) => current_dir.join(name),
+        (None, Some(path)) => current_dir.join(path),
+        (None, None) => current_dir,
+    };
+
+    // Godot classes can't be reused. An existing project might have its own extension_api.json
+    let api = ExtensionApi::load(ws, &path, None)?;
+    api.check_class_name(&naming::to_pascal_case(classname))?;
+    api.check_base_class(&pathargs.base)?;
+
     // Create a new project folder
-    let path = if let Some(name) = projectname {
-        let dir = current_dir.join(name);
-        let _d = dir.clone();
-        let dirname = _d.display();
+    let dirname = path.display();
+    if is_new_project {
         info!("Creating new project folder '{}'", dirname);
-        if let std::result::Result::Ok(_) = std::fs::create_dir(dir.clone()).with_context(|| format!("Tried to create a new folder '{}'", dirname)) {
-        };
-        dir
-    } else {
-        let dir = match p {
-            Some(path) => current_dir.join(path),
-            None => current_dir,
-        };
-        let _d = dir.clone();
-        let dirname = _d.display();
-        if let std::result::Result::Ok(_) = std::fs::create_dir(dir.clone()).with_context(|| format!("Tried to create a new folder '{}'", dirname)) {
-        };
-        dir
     };
+    _ = ws.create_dir(&path).with_context(|| format!("Tried to create a new folder '{}'", dirname));
     let pathstr = path.to_str().unwrap();
-    let read = std::fs::read_dir(path.clone()).with_context(|| format!("Tried reading {}", pathstr))?;
-    let read = read.into_iter().flatten().collect::<Vec<std::fs::DirEntry>>();
+    ws.set_root(&path);
+
+    // A dry-run assumes git exists, so that the plan shows the git commands as well
+    let git_exists = match ws.run(&path, "git --version") {
+        Result::Ok(Some(output)) => output.status.success(),
+        Result::Ok(None) => true,
+        Err(_) => false,
+    };
+    debug!("Testing whether the 'git' command exists: {}", git_exists);
+    let read = ws.read_dir(&path)?;
+
+    let project_exists = read.iter().any(|f| f.file_name().is_some_and(|n| n == "project.godot"));
 
-    // Create the 'godot' folder
-    let godot_folder = path.clone().join(godot_dir);
+    // Create the 'godot' folder. If the Godot project already has a folder with that name, it is moved out of the way first
+    let godot_folder = path.join(godot_dir);
     let godot_folder_str = godot_folder.display().to_string();
-    std::fs::create_dir(godot_folder.clone()).with_context(|| format!("Failed to create directory '{}'", godot_folder_str))?;
+    let existing_godot_folder = if project_exists && ws.exists(&godot_folder) {
+        let tmp = path.join(format!("{}.gdsetup-tmp", godot_dir));
+        ws.rename(&godot_folder, &tmp)?;
+        Some(tmp)
+    } else {
+        None
+    };
+    ws.create_dir_all(godot_folder.parent().unwrap())?;
+    ws.create_dir(&godot_folder).with_context(|| format!("Failed to create directory '{}'", godot_folder_str))?;
 
-    let project_exists = read.iter().any(|f| f.file_name() == "project.godot");
     // If there's a project.godot: Move everything to 'godot/'
     if project_exists {
         info!("Found project.godot. Moving everything inside '{}' into '{}'", pathstr, godot_folder_str);
         for file in read.iter() {
-            let orig = path.join(file.file_name());
-            let orig_disp = orig.display().to_string();
-            let dest = godot_folder.clone().join(file.file_name());
-            let dest_disp = dest.display().to_string();
-            info!("Copying `{}` into `{}` ...", orig_disp, dest_disp);
-            std::fs::copy(orig, dest).with_context(|| format!("Tried copying {} into {}", orig_disp, dest_disp))?;
-        };
-        // Godot project folder has been created, remove old files
-        for file in read.iter() {
-            let filename = path.clone().join(file.file_name());
-            info!("Removing {:?} from '{}' ...", file.file_name().to_str().unwrap(), pathstr);
-            std::fs::remove_file(filename).with_context(|| format!("Failed to remove '{:?}' from {}", file.file_name().to_str().unwrap(), pathstr))?;
+            let filename = file.file_name().unwrap();
+            let filename_str = filename.to_str().unwrap_or_default();
+            if VCS_METADATA.contains(&filename_str) || filename_str == ".gdsetup" || filename_str == MANIFEST_FILE {
+                info!("Leaving `{}` at the root", filename_str);
+                continue;
+            };
+            let orig = match &existing_godot_folder {
+                Some(tmp) if filename_str == godot_dir => tmp.clone(),
+                _ => file.clone(),
+            };
+            move_recursive(ws, &orig, &godot_folder.join(filename))?;
         };
     };
 
+    // The project (or template pack) might bring its own templates
+    if let Some(pack) = &pack {
+        pack.install_templates(ws, &path)?;
+    };
+    let templates = Templates::load(ws, &path)?;
+
     // Create the godot-relevant files for the extension
-    std::fs::write(godot_folder.join(format!("{}.gdextension", classname)), codegen::generate_gdextension(classname))
-        .with_context(||  format!("Tried creating the '{}.gdextension' file.", classname))?;
-    std::fs::create_dir(godot_folder.join(".godot"))
+    ws.write(&godot_folder.join(format!("{}.gdextension", module_name)), codegen::generate_gdextension(&templates, module_name, version)?)
+        .with_context(||  format!("Tried creating the '{}.gdextension' file.", module_name))?;
+    // An existing project might already have a .godot folder (and other extensions)
+    ws.create_dir_all(&godot_folder.join(".godot"))
         .with_context(|| "Tried creating a .godot folder")?;
-    std::fs::write(godot_folder.join(".godot").join("extension_list.cfg"), codegen::generate_gdextension_list(classname))
+    let extension_list_path = godot_folder.join(".godot").join("extension_list.cfg");
+    let extension_list = if ws.exists(&extension_list_path) {
+        codegen::add_to_gdextension_list(&ws.read_to_string(&extension_list_path)?, module_name)
+    } else {
+        codegen::generate_gdextension_list(&templates, module_name, version)?
+    };
+    ws.write(&extension_list_path, extension_list)
         .with_context(|| "Tried creating '.godot/extension_list.cfg'")?;
     
     // Create the 'src/' folder
     let src_folder = path.clone().join(src_dir);
     let src_folder_str = src_folder.display().to_string();
-    std::fs::create_dir(src_folder.clone()).with_context(|| format!("Failed to create directory '{}'", src_folder_str))?;
+    ws.create_dir_all(src_folder.parent().unwrap())?;
+    ws.create_dir(&src_folder).with_context(|| format!("Failed to create directory '{}'", src_folder_str))?;
 
     // Create compilation files
-    std::fs::write(path.clone().join("SConstruct"), codegen::generate_sconstruct(classname))?;
-    std::fs::write(path.clone().join("CMakeLists.txt"), codegen::generate_cmakelists(classname))?;
+    ws.write(&path.join("SConstruct"), codegen::generate_sconstruct(&templates, module_name, godot_dir, src_dir, version)?)?;
+    ws.write(&path.join("CMakeLists.txt"), codegen::generate_cmakelists(&templates, module_name, src_dir, version)?)?;
 
     // Create the registration files
-    std::fs::write(src_folder.join("register_types.cpp"), codegen::generate_register_cpp(classname))?;
-    std::fs::write(src_folder.join("register_types.h"), codegen::generate_register_h(classname))?;
+    let level = InitLevel::for_base(&pathargs.base, &api);
+    ws.write(&src_folder.join("register_types.cpp"), codegen::generate_register_cpp(&templates, module_name, classname, &pathargs.base, level, &api, version)?)?;
+    ws.write(&src_folder.join("register_types.h"), codegen::generate_register_h(&templates, module_name, version)?)?;
     // Create the class files
-    std::fs::write(src_folder.join(format!("{}.cpp", classname)), codegen::generate_class_cpp(classname))?;
-    std::fs::write(src_folder.join(format!("{}.h", classname)), codegen::generate_class_h(classname))?;
+    ws.write(&src_folder.join(format!("{}.cpp", classname)), codegen::generate_class_cpp(&templates, classname, &pathargs.base, &[], &api, version)?)?;
+    ws.write(&src_folder.join(format!("{}.h", classname)), codegen::generate_class_h(&templates, classname, &pathargs.base, &[], &api, version)?)?;
+
+    // Add the files of the template pack
+    if let Some(pack) = &pack {
+        let project_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let context = merge_maps([
+            context! { project_name => project_name },
+            codegen::get_pack_context(module_name, classname, &pathargs.base, &api, godot_dir, src_dir, version),
+            Value::from_serialize(&variables),
+        ]);
+        pack.render_files(ws, &path, context)?;
+    };
+
+    // The undo journal only makes sense on this machine
+    let gitignore_path = path.join(".gitignore");
+    let gitignore = if ws.exists(&gitignore_path) { ws.read_to_string(&gitignore_path)? } else { String::new() };
+    ws.write(&gitignore_path, codegen::add_to_gitignore(&gitignore, &format!("{}/", journal::JOURNAL_DIR)))
+        .with_context(|| "Tried writing '.gitignore'")?;
 
-    let basecmd = get_basecommand();
-    let basecmd = (basecmd.0.as_str(), basecmd.1.as_str());
+    // Describe the project so that other commands don't have to guess file names
+    let mut manifest = Manifest::new(godot_dir, src_dir, version);
+    let mut module = Module::new(module_name, src_dir);
+    module.classes.push(Class {
+        name: naming::to_pascal_case(classname),
+        file: classname.to_string(),
+        base: pathargs.base.clone(),
+        level: None,
+    });
+    manifest.modules.push(module);
+    manifest.save(ws, &path)?;
 
     // Git does not exist, we can't get the godot-cpp submodule and therefore can't build it
     if git_exists {
-        info!("Running 'git init'");
-        let output = Command::new(basecmd.0).arg(basecmd.1)
-            .current_dir(pathstr)
-            .arg("git").arg("init").output().with_context(|| "Tried to 'git init'")?;
-        _ = print_output(output);
-        
-        info!("Running 'git submodule add https://github.com/godotengine/godot-cpp.git'");
-        let output = Command::new(basecmd.0).arg(basecmd.1)
-            .current_dir(pathstr)
-            .arg("git").args(["submodule", "add", "https://github.com/godotengine/godot-cpp.git"]).output().with_context(|| "Tried to find git")?;
-        _ = print_output(output);
-        
+        if let Some(output) = ws.run(&path, "git init").with_context(|| "Tried to 'git init'")? {
+            _ = print_output(output);
+        };
+
+        // Every Godot version has its own godot-cpp branch
+        let submodule = format!("git submodule add -b {} https://github.com/godotengine/godot-cpp.git", version.godot_cpp_branch());
+        if let Some(output) = ws.run(&path, &submodule).with_context(|| "Tried to find git")? {
+            _ = print_output(output);
+        };
+
         if !pathargs.nobuild {
-            info!("Running 'scons'");
-            let output = Command::new(basecmd.0).arg(basecmd.1)
-                .current_dir(pathstr)
-                .arg("scons").output();
-            if let std::result::Result::Ok(_output) = output {
-                //_ = print_output(output);
+            if let Some(output) = ws.run(&path, "scons").ok().flatten() {
+                _ = print_output(output);
             };
         };
     } else {
@@ -130,4 +236,71 @@ pub fn init(pathargs: InitArgs, projectname: Option<String>, classname: &str, go
     };
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::workspace::Operation;
+
+    fn temp_project(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gdsetup-test-{}-{}", name, std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, file).unwrap();
+        };
+        dir
+    }
+
+    #[test]
+    fn moving_merges_into_existing_folders() {
+        let dir = temp_project("move", &["from/a.txt", "from/sub/b.txt", "to/sub/c.txt", "conflict/x.txt", "other/x.txt"]);
+        let mut ws = Workspace::new(false);
+        move_recursive(&mut ws, &dir.join("from"), &dir.join("to")).unwrap();
+        for file in ["to/a.txt", "to/sub/b.txt", "to/sub/c.txt"] {
+            assert!(dir.join(file).is_file(), "{}", file);
+        };
+        assert!(!dir.join("from").exists());
+        // Files are never overwritten
+        assert!(move_recursive(&mut ws, &dir.join("conflict"), &dir.join("other")).is_err());
+        assert_eq!(std::fs::read_to_string(dir.join("other/x.txt")).unwrap(), "other/x.txt");
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn existing_projects_move_into_the_godot_folder() {
+        let files = ["project.godot", "scenes/main.tscn", ".git/HEAD", ".gitignore", "godot/icon.svg", "godot/bin/old.gdextension"];
+        let dir = temp_project("init-existing", &files);
+        let initargs = InitArgs {
+            path: Some(dir.clone()),
+            path2: None,
+            nobuild: true,
+            base: "Node".to_string(),
+            godot_version: "4.3".to_string(),
+            godot_dir: None,
+            src_dir: None,
+            module: None,
+            class: None,
+            template: None,
+            vars: vec![],
+        };
+        let mut ws = Workspace::new(true);
+        init(&mut ws, initargs, None, "example", "godot", "src").unwrap();
+
+        // Version control stays at the root, the rest (even a folder named like the godot folder) moves into it
+        for file in [".git/HEAD", ".gitignore", "godot/project.godot", "godot/scenes/main.tscn", "godot/godot/icon.svg", "godot/godot/bin/old.gdextension"] {
+            assert!(ws.exists(&dir.join(file)), "{}", file);
+        };
+        for file in ["project.godot", "scenes", "godot/icon.svg", "godot.gdsetup-tmp"] {
+            assert!(!ws.exists(&dir.join(file)), "{}", file);
+        };
+        assert!(ws.read_to_string(&dir.join(".gitignore")).unwrap().starts_with(".gitignore"));
+        assert!(ws.operations().iter().any(|op| matches!(op, Operation::Command { command, .. } if command == "git --version")));
+        // It was a dry-run
+        assert!(dir.join("project.godot").is_file() && !dir.join("src").exists());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
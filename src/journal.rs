@@ -0,0 +1,190 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use anyhow::{Result, Error, Context};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::workspace::{Operation, Workspace};
+
+
+/// Folder (relative to the project root) which contains the journal of the last command
+pub const JOURNAL_DIR: &str = ".gdsetup/undo";
+const JOURNAL_FILE: &str = "journal.toml";
+
+
+/// On-disk version of an Operation. File contents are stored as separate backup files next to the journal.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Entry {
+    CreateDir { path: PathBuf },
+    CreateFile { path: PathBuf },
+    ModifyFile { path: PathBuf, backup: String },
+    Copy { from: PathBuf, to: PathBuf, backup: Option<String> },
+    Move { from: PathBuf, to: PathBuf },
+    RemoveFile { path: PathBuf, backup: String },
+    RemoveDir { path: PathBuf },
+    Command { cwd: PathBuf, command: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Journal {
+    #[serde(default, rename = "operation")]
+    entries: Vec<Entry>,
+    /// Hashes of the files the command left behind, to find out whether they have been edited since
+    #[serde(default)]
+    hashes: BTreeMap<PathBuf, String>,
+}
+
+
+/// Paths are stored relative to the project root, so that the project can be moved
+fn relative(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root).map(|p| p.to_path_buf()).unwrap_or_else(|_| path.to_path_buf())
+}
+
+
+/// FNV-1a, it only has to notice edits, not withstand attacks
+fn hash(contents: &[u8]) -> String {
+    let hash = contents.iter().fold(0xcbf29ce484222325_u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+
+/// Returns the files an entry writes to, moves away or removes. Undoing it overwrites them
+fn written_paths(entry: &Entry) -> Vec<&Path> {
+    match entry {
+        Entry::CreateFile { path } | Entry::ModifyFile { path, .. } | Entry::RemoveFile { path, .. } => vec![path],
+        Entry::Copy { to, .. } => vec![to],
+        Entry::Move { from, to } => vec![from, to],
+        _ => vec![],
+    }
+}
+
+
+/// Returns the files which have been edited (or created again) since the command ran
+fn find_edited(root: &Path, journal: &Journal) -> Vec<PathBuf> {
+    let mut edited = vec![];
+    for path in journal.entries.iter().flat_map(written_paths) {
+        // Folders can't be read, files which are gone can't be overwritten
+        let Ok(contents) = std::fs::read(root.join(path)) else {
+            continue;
+        };
+        // Files without a hash were gone after the command
+        let is_edited = journal.hashes.get(path).is_none_or(|expected| *expected != hash(&contents));
+        if is_edited && !edited.iter().any(|p| p == path) {
+            edited.push(path.to_path_buf());
+        };
+    };
+    edited
+}
+
+
+/// Saves the operations of the workspace as the journal of its root folder, replacing the previous one.
+/// Does nothing in dry-run mode, without a root folder or if nothing has been done.
+pub fn save(ws: &Workspace) -> Result<(), Error> {
+    let Some(root) = ws.root() else {
+        return Ok(());
+    };
+    if ws.is_dry_run() || ws.operations().is_empty() || !root.is_dir() {
+        return Ok(());
+    };
+
+    let journal_dir = root.join(JOURNAL_DIR);
+    if journal_dir.exists() {
+        std::fs::remove_dir_all(&journal_dir).with_context(|| format!("Tried removing the old journal '{}'", journal_dir.display()))?;
+    };
+    std::fs::create_dir_all(&journal_dir).with_context(|| format!("Tried creating '{}'", journal_dir.display()))?;
+
+    let mut backups = 0;
+    let mut backup = |contents: &[u8]| -> Result<String, Error> {
+        let name = format!("{}.bak", backups);
+        backups += 1;
+        std::fs::write(journal_dir.join(&name), contents).with_context(|| format!("Tried writing backup '{}'", name))?;
+        Ok(name)
+    };
+    let mut entries = vec![];
+    for operation in ws.operations() {
+        let entry = match operation {
+            Operation::CreateDir(path) => Entry::CreateDir { path: relative(root, path) },
+            Operation::CreateFile { path, .. } => Entry::CreateFile { path: relative(root, path) },
+            Operation::ModifyFile { path, old, .. } => Entry::ModifyFile { path: relative(root, path), backup: backup(old)? },
+            Operation::Copy { from, to, old } => Entry::Copy {
+                from: relative(root, from),
+                to: relative(root, to),
+                backup: old.as_ref().map(|old| backup(old)).transpose()?,
+            },
+            Operation::Move { from, to } => Entry::Move { from: relative(root, from), to: relative(root, to) },
+            Operation::RemoveFile { path, contents } => Entry::RemoveFile { path: relative(root, path), backup: backup(contents)? },
+            Operation::RemoveDir(path) => Entry::RemoveDir { path: relative(root, path) },
+            Operation::Command { cwd, command } => Entry::Command { cwd: relative(root, cwd), command: command.clone() },
+        };
+        entries.push(entry);
+    };
+    let mut hashes = BTreeMap::new();
+    for path in entries.iter().flat_map(written_paths) {
+        if let Ok(contents) = std::fs::read(root.join(path)) {
+            hashes.insert(path.to_path_buf(), hash(&contents));
+        };
+    };
+    let journal = Journal { entries, hashes };
+
+    let contents = toml::to_string_pretty(&journal).with_context(|| "Tried serializing the journal")?;
+    std::fs::write(journal_dir.join(JOURNAL_FILE), contents).with_context(|| "Tried writing the journal")?;
+    debug!("Saved the journal of {} operation(s) to '{}'", journal.entries.len(), journal_dir.display());
+    Ok(())
+}
+
+
+/// Loads the journal of the last command that ran inside 'root'.
+/// Refuses if files have been edited since, as undoing would overwrite or delete them, unless 'force' is set
+pub fn load(root: &Path, force: bool) -> Result<Vec<Operation>, Error> {
+    let journal_dir = root.join(JOURNAL_DIR);
+    let journal_path = journal_dir.join(JOURNAL_FILE);
+    let contents = std::fs::read_to_string(&journal_path)
+        .with_context(|| format!("Could not find a journal at '{}'. There is nothing to undo.", journal_path.display()))?;
+    let journal: Journal = toml::from_str(&contents).with_context(|| format!("Tried parsing '{}'", journal_path.display()))?;
+
+    let edited = find_edited(root, &journal);
+    if !edited.is_empty() {
+        let edited = edited.iter().map(|path| format!("'{}'", path.display())).collect::<Vec<String>>().join(", ");
+        if !force {
+            return Err(Error::msg(format!("These files have been edited since the last command, undoing it would overwrite them: {}. Use --force to undo anyway.", edited)));
+        };
+        warn!("Overwriting the edited files {}", edited);
+    };
+
+    let backup = |name: &str| -> Result<Vec<u8>, Error> {
+        std::fs::read(journal_dir.join(name)).with_context(|| format!("Tried reading backup '{}'", name))
+    };
+    let mut operations = vec![];
+    for entry in journal.entries {
+        let operation = match entry {
+            Entry::CreateDir { path } => Operation::CreateDir(root.join(path)),
+            Entry::CreateFile { path } => Operation::CreateFile { path: root.join(path), contents: vec![] },
+            Entry::ModifyFile { path, backup: name } => Operation::ModifyFile { path: root.join(path), old: backup(&name)?, new: vec![] },
+            Entry::Copy { from, to, backup: name } => Operation::Copy {
+                from: root.join(from),
+                to: root.join(to),
+                old: name.map(|name| backup(&name)).transpose()?,
+            },
+            Entry::Move { from, to } => Operation::Move { from: root.join(from), to: root.join(to) },
+            Entry::RemoveFile { path, backup: name } => Operation::RemoveFile { path: root.join(path), contents: backup(&name)? },
+            Entry::RemoveDir { path } => Operation::RemoveDir(root.join(path)),
+            Entry::Command { cwd, command } => Operation::Command { cwd: root.join(cwd), command },
+        };
+        operations.push(operation);
+    };
+    Ok(operations)
+}
+
+
+/// Removes the journal (and the '.gdsetup' folder if nothing else is inside)
+pub fn clear(root: &Path) -> Result<(), Error> {
+    let journal_dir = root.join(JOURNAL_DIR);
+    if journal_dir.exists() {
+        std::fs::remove_dir_all(&journal_dir).with_context(|| format!("Tried removing '{}'", journal_dir.display()))?;
+    };
+    if let Some(parent) = journal_dir.parent() {
+        _ = std::fs::remove_dir(parent);
+    };
+    Ok(())
+}
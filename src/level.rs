@@ -0,0 +1,332 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::{Result, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{api::ExtensionApi, regions, version::GodotVersion};
+
+
+/// The levels at which Godot initializes extensions, in the order it runs them
+pub const INIT_LEVELS: [&str; 4] = ["core", "servers", "scene", "editor"];
+/// Declares 'EditorPlugins', see [get_plugin_registration]
+pub const EDITOR_PLUGIN_INCLUDE: &str = "#include <godot_cpp/classes/editor_plugin_registration.hpp>";
+const LEVEL_PREFIX: &str = "MODULE_INITIALIZATION_LEVEL_";
+/// Projects generated before the levels existed return early from their initializer for anything but the scene level
+const SCENE_GUARD: [&str; 3] = ["if (p_level != MODULE_INITIALIZATION_LEVEL_SCENE) {", "return;", "}"];
+
+
+/// The registrations of a module, grouped by the level which runs them
+pub type Registrations = BTreeMap<InitLevel, Vec<String>>;
+
+
+/// The initialization level a class is registered at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum InitLevel {
+    Core,
+    Servers,
+    #[default]
+    Scene,
+    /// Only runs inside the editor, needed by classes which inherit from editor classes like EditorPlugin
+    Editor,
+}
+
+
+impl InitLevel {
+    /// The godot-cpp constant of the level, e.g. 'MODULE_INITIALIZATION_LEVEL_SCENE'
+    pub fn constant(&self) -> String {
+        format!("{}{}", LEVEL_PREFIX, self.to_string().to_uppercase())
+    }
+
+    /// Parses constants like 'MODULE_INITIALIZATION_LEVEL_EDITOR'
+    pub fn from_constant(constant: &str) -> Option<Self> {
+        constant.trim().strip_prefix(LEVEL_PREFIX)?.to_lowercase().parse().ok()
+    }
+
+    /// Classes inheriting from an editor class only exist inside the editor, everything else is registered with the scene
+    pub fn for_base(base: &str, api: &ExtensionApi) -> Self {
+        if api.is_editor_class(base) {
+            InitLevel::Editor
+        } else {
+            InitLevel::Scene
+        }
+    }
+}
+
+
+impl FromStr for InitLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "core" => Ok(InitLevel::Core),
+            "servers" => Ok(InitLevel::Servers),
+            "scene" => Ok(InitLevel::Scene),
+            "editor" => Ok(InitLevel::Editor),
+            _ => Err(Error::msg(format!("'{}' is not an initialization level, use one of: {}.", s, INIT_LEVELS.join(", ")))),
+        }
+    }
+}
+
+impl TryFrom<String> for InitLevel {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<InitLevel> for String {
+    fn from(value: InitLevel) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for InitLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InitLevel::Core => "core",
+            InitLevel::Servers => "servers",
+            InitLevel::Scene => "scene",
+            InitLevel::Editor => "editor",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+
+/// Since 4.1 'EditorPlugins::add_by_type' adds a plugin to the editor, it has to run at the editor level
+pub fn get_plugin_registration(classname: &str, base: &str, version: GodotVersion) -> Option<String> {
+    (base == "EditorPlugin" && version.has_editor_plugins()).then(|| format!("EditorPlugins::add_by_type<{}>();", classname))
+}
+
+
+/// The lowest level anything is registered at, Godot does not call the initializer before it
+pub fn get_minimum_level(registrations: &Registrations) -> InitLevel {
+    registrations.iter()
+        .find(|(_, lines)| !lines.is_empty())
+        .map(|(level, _)| *level)
+        .unwrap_or_default()
+}
+
+
+/// Reads the lines of a 'registrations' region. Lines outside of an 'if (p_level == ...) {' block belong to the scene level.
+/// Braces opened inside a block (e.g. by an 'if' of the user) keep it open, their lines stay indented
+pub fn parse_registrations(lines: &[String]) -> Registrations {
+    let mut registrations = Registrations::new();
+    let mut block = None;
+    let mut depth = 0usize;
+    for line in lines.iter().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        if depth == 0 {
+            let opened = line.strip_prefix("if (p_level == ")
+                .and_then(|rest| rest.strip_suffix(") {"))
+                .and_then(InitLevel::from_constant);
+            if block.is_none() && opened.is_some() {
+                block = opened;
+                continue;
+            };
+            if block.is_some() && line == "}" {
+                block = None;
+                continue;
+            };
+        };
+        let indent = if line.starts_with('}') { depth.saturating_sub(1) } else { depth };
+        depth = (depth + line.matches('{').count()).saturating_sub(line.matches('}').count());
+        registrations.entry(block.unwrap_or_default()).or_default().push(format!("{}{}", "    ".repeat(indent), line));
+    };
+    registrations
+}
+
+
+/// One 'if (p_level == ...) { ... }' block per level, lowest level first
+pub fn format_registrations(registrations: &Registrations) -> Vec<String> {
+    let mut lines = vec![];
+    for (level, registered) in registrations.iter().filter(|(_, lines)| !lines.is_empty()) {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        };
+        lines.push(format!("if (p_level == {}) {{", level.constant()));
+        lines.extend(registered.iter().map(|line| format!("    {}", line)));
+        lines.push("}".to_string());
+    };
+    lines
+}
+
+
+/// Rewrites the 'includes' and 'registrations' regions of a register_types.cpp. The minimum initialization level and the
+/// level the uninitializer runs at become the lowest level which registers something
+pub fn set_registrations(register_cpp: &str, includes: &[String], registrations: &Registrations) -> Result<String, Error> {
+    let mut includes = includes.iter().filter(|include| *include != EDITOR_PLUGIN_INCLUDE).cloned().collect::<Vec<String>>();
+    if registrations.values().flatten().any(|line| line.starts_with("EditorPlugins::")) {
+        includes.insert(0, EDITOR_PLUGIN_INCLUDE.to_string());
+    };
+    let contents = regions::replace_region(register_cpp, "includes", &includes)?;
+    let contents = regions::replace_region(&contents, "registrations", &format_registrations(registrations))?;
+    let contents = remove_scene_guard(&contents);
+    let minimum_level = get_minimum_level(registrations);
+    Ok(set_uninitialize_level(&set_minimum_level(&contents, minimum_level), minimum_level))
+}
+
+
+/// Removes the early return for every level but the scene one from the initializer, the blocks check the level themselves
+fn remove_scene_guard(contents: &str) -> String {
+    let Some((start, _)) = regions::find_region(contents, "registrations") else {
+        return contents.to_string();
+    };
+    let mut lines = contents.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    let guard = (0..start.saturating_sub(2)).rev()
+        .take_while(|idx| !lines[*idx].starts_with("void "))
+        .find(|idx| SCENE_GUARD.iter().enumerate().all(|(offset, line)| lines[idx + offset].trim() == *line));
+    if let Some(idx) = guard {
+        lines.drain(idx..idx + SCENE_GUARD.len());
+        if lines.get(idx).is_some_and(|l| l.trim().is_empty()) {
+            lines.remove(idx);
+        };
+    };
+    regions::join_lines(lines, contents)
+}
+
+
+/// Points 'set_minimum_library_initialization_level' to 'level'
+fn set_minimum_level(contents: &str, level: InitLevel) -> String {
+    let call = "set_minimum_library_initialization_level(";
+    let lines = contents.lines()
+        .map(|line| match line.find(call) {
+            Some(idx) => format!("{}{});", &line[..idx + call.len()], level.constant()),
+            None => line.to_string(),
+        })
+        .collect::<Vec<String>>();
+    regions::join_lines(lines, contents)
+}
+
+
+/// Points the 'if (p_level != ...) {' guard of the uninitializer to 'level', the last level Godot uninitializes
+fn set_uninitialize_level(contents: &str, level: InitLevel) -> String {
+    let mut in_uninitializer = false;
+    let lines = contents.lines()
+        .map(|line| {
+            if line.starts_with("void uninitialize_") {
+                in_uninitializer = true;
+            } else if line.starts_with('}') {
+                in_uninitializer = false;
+            };
+            let guard = line.trim().strip_prefix("if (p_level != ").and_then(|rest| rest.strip_suffix(") {"));
+            match guard {
+                Some(constant) if in_uninitializer && InitLevel::from_constant(constant).is_some() => {
+                    let indent = &line[..line.len() - line.trim_start().len()];
+                    format!("{}if (p_level != {}) {{", indent, level.constant())
+                },
+                _ => line.to_string(),
+            }
+        })
+        .collect::<Vec<String>>();
+    regions::join_lines(lines, contents)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_level_blocks() {
+        let registrations = parse_registrations(&lines("\
+    ClassDB::register_class<Legacy>();
+    if (p_level == MODULE_INITIALIZATION_LEVEL_CORE) {
+        ClassDB::register_class<Core>();
+    }
+
+    if (p_level == MODULE_INITIALIZATION_LEVEL_EDITOR) {
+        EditorPlugins::add_by_type<Plugin>();
+    }
+"));
+        assert_eq!(registrations[&InitLevel::Scene], vec!["ClassDB::register_class<Legacy>();"]);
+        assert_eq!(registrations[&InitLevel::Core], vec!["ClassDB::register_class<Core>();"]);
+        assert_eq!(registrations[&InitLevel::Editor], vec!["EditorPlugins::add_by_type<Plugin>();"]);
+        assert_eq!(get_minimum_level(&registrations), InitLevel::Core);
+    }
+
+    #[test]
+    fn nested_braces_stay_inside_their_block() {
+        let registrations = parse_registrations(&lines("\
+if (p_level == MODULE_INITIALIZATION_LEVEL_SCENE) {
+    if (Engine::get_singleton()->is_editor_hint()) {
+        ClassDB::register_class<Tool>();
+    }
+    ClassDB::register_class<After>();
+}
+"));
+        assert_eq!(registrations.len(), 1);
+        assert_eq!(registrations[&InitLevel::Scene], vec![
+            "if (Engine::get_singleton()->is_editor_hint()) {",
+            "    ClassDB::register_class<Tool>();",
+            "}",
+            "ClassDB::register_class<After>();",
+        ]);
+        // Formatting keeps the nesting
+        assert_eq!(format_registrations(&registrations)[1..4], [
+            "    if (Engine::get_singleton()->is_editor_hint()) {",
+            "        ClassDB::register_class<Tool>();",
+            "    }",
+        ]);
+    }
+
+    #[test]
+    fn formatting_and_parsing_round_trip() {
+        let mut registrations = Registrations::new();
+        registrations.insert(InitLevel::Editor, vec!["ClassDB::register_class<Plugin>();".to_string()]);
+        registrations.insert(InitLevel::Servers, vec!["ClassDB::register_class<Server>();".to_string()]);
+        registrations.insert(InitLevel::Scene, vec![]);
+        let formatted = format_registrations(&registrations);
+        assert_eq!(formatted.iter().filter(|l| l.starts_with("if (")).count(), 2);
+        registrations.remove(&InitLevel::Scene);
+        assert_eq!(parse_registrations(&formatted), registrations);
+        assert_eq!(get_minimum_level(&Registrations::new()), InitLevel::Scene);
+    }
+
+    #[test]
+    fn sets_the_minimum_level() {
+        let contents = "    init_obj.set_minimum_library_initialization_level(MODULE_INITIALIZATION_LEVEL_SCENE);\n";
+        assert_eq!(set_minimum_level(contents, InitLevel::Core), "    init_obj.set_minimum_library_initialization_level(MODULE_INITIALIZATION_LEVEL_CORE);\n");
+    }
+
+    #[test]
+    fn rewrites_legacy_register_types() {
+        let register_cpp = "\
+// gdsetup:begin includes
+#include \"player.h\"
+// gdsetup:end
+
+void initialize_example_module(ModuleInitializationLevel p_level) {
+    if (p_level != MODULE_INITIALIZATION_LEVEL_SCENE) {
+        return;
+    }
+
+    // gdsetup:begin registrations
+    ClassDB::register_class<Player>();
+    // gdsetup:end
+}
+
+void uninitialize_example_module(ModuleInitializationLevel p_level) {
+    if (p_level != MODULE_INITIALIZATION_LEVEL_SCENE) {
+        return;
+    }
+}
+
+    init_obj.set_minimum_library_initialization_level(MODULE_INITIALIZATION_LEVEL_SCENE);
+";
+        let mut registrations = parse_registrations(&regions::read_region(register_cpp, "registrations").unwrap());
+        registrations.entry(InitLevel::Servers).or_default().push("ClassDB::register_class<Server>();".to_string());
+        let includes = vec!["#include \"player.h\"".to_string(), "#include \"server.h\"".to_string()];
+        let updated = set_registrations(register_cpp, &includes, &registrations).unwrap();
+        assert!(updated.contains("void initialize_example_module(ModuleInitializationLevel p_level) {\n    // gdsetup:begin registrations\n    if (p_level == MODULE_INITIALIZATION_LEVEL_SERVERS) {"), "{}", updated);
+        assert!(updated.contains("#include \"server.h\""));
+        assert!(updated.contains("uninitialize_example_module(ModuleInitializationLevel p_level) {\n    if (p_level != MODULE_INITIALIZATION_LEVEL_SERVERS) {"));
+        assert!(updated.contains("set_minimum_library_initialization_level(MODULE_INITIALIZATION_LEVEL_SERVERS);"));
+        assert!(!updated.contains(EDITOR_PLUGIN_INCLUDE));
+    }
+}
@@ -4,6 +4,16 @@ use log::debug;
 
 pub use cli::*;
 pub mod codegen;
+pub mod naming;
+pub mod manifest;
+pub mod workspace;
+pub mod journal;
+pub mod version;
+pub mod templates;
+pub mod pack;
+pub mod api;
+pub mod regions;
+pub mod level;
 
 mod init;
 pub use init::init;
@@ -12,7 +22,19 @@ mod rename;
 pub use rename::rename;
 
 mod add;
-pub use add::add_extension;
+pub use add::{add, add_extension, add_class};
+
+mod members;
+mod header;
+
+mod undo;
+pub use undo::undo;
+mod upgrade;
+pub use upgrade::upgrade;
+mod sync;
+pub use sync::sync;
+mod bind;
+pub use bind::bind;
 
 
 pub fn print_output(output: std::process::Output) -> Result<(), Error> {
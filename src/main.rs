@@ -2,14 +2,13 @@ use std::{str::FromStr};
 
 use anyhow::{Result, Error};
 use clap::Parser;
-use log::debug;
+use log::{debug, warn};
 
-use gdsetup::{init, Commands, GDSetup, InitArgs, add_extension, rename};
+use gdsetup::{codegen, version, init, Commands, GDSetup, InitArgs, add, rename, undo, upgrade, sync, bind, journal, workspace::Workspace};
 
 const GODOT_DIR: &str = "godot";
 const SRC_DIR: &str = "src";
 const CLASS_NAME: &str = "example";
-const DEFAULT_PROJECTNAME: String = String::new();
 
 
 // Create folders:
@@ -39,14 +38,46 @@ fn main() -> Result<(), Error> {
     env_logger::init_from_env(env_logger::Env::new());
 
     debug!("{args:?}");
-    if let Some(command) = args.command {
+    let mut ws = Workspace::new(args.dry_run);
+    // Undoing should not replace the journal it has just reverted
+    let journaled = !matches!(args.command, Some(Commands::Undo(_)));
+    let result = if let Some(command) = args.command {
         match command {
-            Commands::Init(pathargs) => init(pathargs, args.name, CLASS_NAME, GODOT_DIR, SRC_DIR),
-            Commands::Rename(nameargs) => rename(nameargs),
-            Commands::Add(nameargs) => add_extension(nameargs),
+            Commands::Init(pathargs) => init(&mut ws, pathargs, args.name, CLASS_NAME, GODOT_DIR, SRC_DIR),
+            Commands::Rename(nameargs) => rename(&mut ws, nameargs, GODOT_DIR, SRC_DIR),
+            Commands::Add(nameargs) => add(&mut ws, nameargs, GODOT_DIR, SRC_DIR),
+            Commands::Undo(undoargs) => undo(&mut ws, undoargs),
+            Commands::Upgrade(upgradeargs) => upgrade(&mut ws, upgradeargs, GODOT_DIR, SRC_DIR),
+            Commands::Sync(syncargs) => sync(&mut ws, syncargs, SRC_DIR),
+            Commands::Bind(bindargs) => bind(&mut ws, bindargs, SRC_DIR),
         }
     } else {
-        init(InitArgs { path: None, path2: None, nobuild: false}, args.name, CLASS_NAME, GODOT_DIR, SRC_DIR)
-    }
+        let initargs = InitArgs {
+            path: None,
+            path2: None,
+            nobuild: false,
+            base: codegen::DEFAULT_BASE_CLASS.to_string(),
+            godot_version: version::DEFAULT_VERSION.to_string(),
+            godot_dir: None,
+            src_dir: None,
+            module: None,
+            class: None,
+            template: None,
+            vars: vec![],
+        };
+        init(&mut ws, initargs, args.name, CLASS_NAME, GODOT_DIR, SRC_DIR)
+    };
 
-}
+    // Nothing has been touched, show what would have happened
+    if ws.is_dry_run() {
+        ws.print_plan();
+    } else if result.is_err() {
+        // Don't leave a half-finished project behind
+        if let Err(err) = ws.rollback() {
+            warn!("Rolling back failed, the project might be left in an inconsistent state: {}", err);
+        };
+    } else if journaled {
+        journal::save(&ws)?;
+    };
+    result
+}
\ No newline at end of file
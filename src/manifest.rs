@@ -0,0 +1,129 @@
+use std::path::Path;
+
+use anyhow::{Result, Error, Context};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{level::InitLevel, version::GodotVersion, workspace::Workspace};
+
+
+/// Name of the manifest file inside the project root
+pub const MANIFEST_FILE: &str = "gdsetup.toml";
+
+
+/// Describes a project created by gdsetup. Every command should read and update this instead of guessing file names.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub project: Project,
+    #[serde(default, rename = "module")]
+    pub modules: Vec<Module>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Project {
+    /// Folder (relative to the project root) which contains the Godot project
+    pub godot_dir: String,
+    /// Folder (relative to the project root) which contains the C++ sources
+    pub src_dir: String,
+    /// Build systems gdsetup generated files for (e.g. 'scons', 'cmake')
+    pub build_systems: Vec<String>,
+    /// Godot version the generated code targets
+    #[serde(default = "legacy_godot_version")]
+    pub godot_version: GodotVersion,
+    /// Branch or tag of the godot-cpp submodule
+    pub godot_cpp_version: String,
+    /// extension_api.json (relative to the project root) to check class names against, e.g. one written by 'godot --dump-extension-api'
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extension_api: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Module {
+    pub name: String,
+    /// Name of the C function Godot calls to initialize the library
+    pub entry_symbol: String,
+    /// Folder (relative to the project root) which contains the sources of this module
+    pub src_dir: String,
+    /// Path of the .gdextension file (relative to the godot folder)
+    pub gdextension: String,
+    #[serde(default, rename = "class")]
+    pub classes: Vec<Class>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Class {
+    /// Name of the C++ class
+    pub name: String,
+    /// Name of the .h/.cpp files (without extension)
+    pub file: String,
+    /// Godot class this class inherits from
+    pub base: String,
+    /// Initialization level the class is registered at. Without it the level follows from the base class
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<InitLevel>,
+}
+
+
+/// Manifests written before the Godot version was recorded belong to projects generated with the 4.0 templates
+fn legacy_godot_version() -> GodotVersion {
+    GodotVersion::V4_0
+}
+
+
+impl Manifest {
+    pub fn new(godot_dir: &str, src_dir: &str, godot_version: GodotVersion) -> Self {
+        Manifest {
+            project: Project {
+                godot_dir: godot_dir.to_string(),
+                src_dir: src_dir.to_string(),
+                build_systems: vec!["scons".to_string(), "cmake".to_string()],
+                godot_version,
+                godot_cpp_version: godot_version.godot_cpp_branch(),
+                extension_api: None,
+            },
+            modules: vec![],
+        }
+    }
+
+    /// Reads the manifest of the project at 'path'. Returns None for projects which do not have one (yet).
+    pub fn load(ws: &Workspace, path: &Path) -> Result<Option<Manifest>, Error> {
+        let manifest_path = path.join(MANIFEST_FILE);
+        if !ws.exists(&manifest_path) {
+            debug!("No {} found inside '{}'", MANIFEST_FILE, path.display());
+            return Ok(None);
+        };
+        let contents = ws.read_to_string(&manifest_path)
+            .with_context(|| format!("Tried reading '{}'", manifest_path.display()))?;
+        let manifest = toml::from_str(&contents)
+            .with_context(|| format!("Tried parsing '{}'", manifest_path.display()))?;
+        Ok(Some(manifest))
+    }
+
+    pub fn save(&self, ws: &mut Workspace, path: &Path) -> Result<(), Error> {
+        let manifest_path = path.join(MANIFEST_FILE);
+        let contents = toml::to_string_pretty(self).with_context(|| "Tried serializing the manifest")?;
+        ws.write(&manifest_path, contents)
+            .with_context(|| format!("Tried writing '{}'", manifest_path.display()))
+    }
+
+    pub fn find_module(&self, name: &str) -> Option<&Module> {
+        self.modules.iter().find(|m| m.name == name)
+    }
+
+    pub fn find_module_mut(&mut self, name: &str) -> Option<&mut Module> {
+        self.modules.iter_mut().find(|m| m.name == name)
+    }
+}
+
+
+impl Module {
+    pub fn new(name: &str, src_dir: &str) -> Self {
+        Module {
+            name: name.to_string(),
+            entry_symbol: format!("{}_library_init", name),
+            src_dir: src_dir.to_string(),
+            gdextension: format!("{}.gdextension", name),
+            classes: vec![],
+        }
+    }
+}
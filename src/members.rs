@@ -0,0 +1,601 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Error, Context};
+use log::info;
+
+use crate::{AddConstantArgs, AddEnumArgs, AddMethodArgs, AddPropertyArgs, AddSignalArgs, bind, naming, header::{self, Param, Signature}, manifest::Manifest, regions, version::GodotVersion, workspace::Workspace};
+
+
+/// Types a property (or signal argument) can have and their 'Variant::Type'
+const VARIANT_TYPES: [(&str, &str); 43] = [
+    ("bool", "BOOL"), ("int", "INT"), ("int32_t", "INT"), ("int64_t", "INT"), ("float", "FLOAT"), ("double", "FLOAT"),
+    ("String", "STRING"), ("Vector2", "VECTOR2"), ("Vector2i", "VECTOR2I"), ("Rect2", "RECT2"), ("Rect2i", "RECT2I"),
+    ("Vector3", "VECTOR3"), ("Vector3i", "VECTOR3I"), ("Transform2D", "TRANSFORM2D"), ("Vector4", "VECTOR4"), ("Vector4i", "VECTOR4I"),
+    ("Plane", "PLANE"), ("Quaternion", "QUATERNION"), ("AABB", "AABB"), ("Basis", "BASIS"), ("Transform3D", "TRANSFORM3D"),
+    ("Projection", "PROJECTION"), ("Color", "COLOR"), ("StringName", "STRING_NAME"), ("NodePath", "NODE_PATH"), ("RID", "RID"),
+    ("Object", "OBJECT"), ("Callable", "CALLABLE"), ("Signal", "SIGNAL"), ("Dictionary", "DICTIONARY"), ("Array", "ARRAY"),
+    ("PackedByteArray", "PACKED_BYTE_ARRAY"), ("PackedInt32Array", "PACKED_INT32_ARRAY"), ("PackedInt64Array", "PACKED_INT64_ARRAY"),
+    ("PackedFloat32Array", "PACKED_FLOAT32_ARRAY"), ("PackedFloat64Array", "PACKED_FLOAT64_ARRAY"), ("PackedStringArray", "PACKED_STRING_ARRAY"),
+    ("PackedVector2Array", "PACKED_VECTOR2_ARRAY"), ("PackedVector3Array", "PACKED_VECTOR3_ARRAY"), ("PackedColorArray", "PACKED_COLOR_ARRAY"),
+    ("PackedVector4Array", "PACKED_VECTOR4_ARRAY"), ("Variant", "NIL"), ("real_t", "FLOAT"),
+];
+
+/// The 'PropertyHint's of Godot 4, without the 'PROPERTY_HINT_' prefix
+const PROPERTY_HINTS: [&str; 38] = [
+    "NONE", "RANGE", "ENUM", "ENUM_SUGGESTION", "EXP_EASING", "LINK", "FLAGS", "LAYERS_2D_RENDER",
+    "LAYERS_2D_PHYSICS", "LAYERS_2D_NAVIGATION", "LAYERS_3D_RENDER", "LAYERS_3D_PHYSICS", "LAYERS_3D_NAVIGATION", "LAYERS_AVOIDANCE",
+    "FILE", "DIR", "GLOBAL_FILE", "GLOBAL_DIR", "RESOURCE_TYPE", "MULTILINE_TEXT", "EXPRESSION", "PLACEHOLDER_TEXT",
+    "COLOR_NO_ALPHA", "OBJECT_ID", "TYPE_STRING", "NODE_PATH_TO_EDITED_NODE", "OBJECT_TOO_BIG", "NODE_PATH_VALID_TYPES",
+    "SAVE_FILE", "GLOBAL_SAVE_FILE", "INT_IS_OBJECTID", "INT_IS_POINTER", "ARRAY_TYPE", "LOCALE_ID",
+    "LOCALIZABLE_STRING", "NODE_TYPE", "HIDE_QUATERNION_EDIT", "PASSWORD",
+];
+
+
+/// The header and source of a class
+pub struct ClassFiles {
+    /// Name of the C++ class
+    pub name: String,
+    pub header: PathBuf,
+    pub source: PathBuf,
+}
+
+/// What gets added to a class. Each part has its own place inside the header or source
+#[derive(Default)]
+struct ClassEdit {
+    /// Private members
+    members: Vec<String>,
+    /// Public declarations
+    declarations: Vec<String>,
+    /// Lines following the class declaration, e.g. 'VARIANT_ENUM_CAST'
+    casts: Vec<String>,
+    /// Definitions in front of '_bind_methods'
+    definitions: Vec<String>,
+    /// Lines at the end of '_bind_methods'
+    bindings: Vec<String>,
+}
+
+
+
+pub fn add_method(ws: &mut Workspace, path: &Path, methodargs: &AddMethodArgs, src_dir: &str, manifest: Option<&Manifest>) -> Result<(), Error> {
+    let mut signature = parse_signature(&methodargs.signature)?;
+    signature.is_static = methodargs.is_static;
+    if signature.is_static && signature.is_const {
+        return Err(Error::msg(format!("The static method '{}' can't be const.", signature.name)));
+    };
+    let class = find_class_files(ws, path, &methodargs.class, src_dir, manifest)?;
+    check_method_free(ws, &class, &signature.name)?;
+
+    // Declaration, definition and binding
+    let params = signature.params.iter().map(|p| header::format_param(p, true)).collect::<Vec<String>>().join(", ");
+    let declaration = format!("{}{} {}({}){};",
+        if signature.is_static { "static " } else { "" }, signature.return_type, signature.name, params, if signature.is_const { " const" } else { "" });
+    let params = signature.params.iter().map(|p| header::format_param(p, false)).collect::<Vec<String>>().join(", ");
+    let mut definition = vec![format!("{} {}::{}({}){} {{", signature.return_type, class.name, signature.name, params, if signature.is_const { " const" } else { "" })];
+    if signature.return_type != "void" {
+        definition.push("    return {};".to_string());
+    };
+    definition.push("}".to_string());
+    let binding = get_method_binding(&class.name, &signature);
+
+    info!("Adding method '{}' to '{}'", signature.name, class.name);
+    edit_class(ws, &class, ClassEdit { declarations: vec![declaration], definitions: definition, bindings: vec![binding], ..Default::default() })
+}
+
+
+pub fn add_property(ws: &mut Workspace, path: &Path, propertyargs: &AddPropertyArgs, src_dir: &str, manifest: Option<&Manifest>) -> Result<(), Error> {
+    let name = &propertyargs.name;
+    naming::validate_identifier(name, "property")?;
+    let property_type = propertyargs.property_type.as_str();
+    let variant_type = get_variant_type(property_type)?;
+    if property_type == "Variant" || property_type == "Object" {
+        return Err(Error::msg(format!("Properties of the type '{}' are not supported, use a concrete type like 'float' or 'String'.", property_type)));
+    };
+    let hint = match &propertyargs.hint {
+        Some(hint) => get_property_hint(hint)?,
+        None if propertyargs.hint_string.is_some() => return Err(Error::msg("'--hint-string' needs a '--hint', e.g. '--hint range --hint-string \"0,100,1\"'.")),
+        None => "PROPERTY_HINT_NONE".to_string(),
+    };
+    let class = find_class_files(ws, path, &propertyargs.class, src_dir, manifest)?;
+    let (setter, getter) = (format!("set_{}", name), format!("get_{}", name));
+    check_method_free(ws, &class, &setter)?;
+    check_method_free(ws, &class, &getter)?;
+
+    // Scalars get an initial value, everything else is passed by reference
+    let (member, param) = match property_type {
+        "bool" => (format!("bool {} = false;", name), format!("bool p_{}", name)),
+        "float" | "double" | "real_t" => (format!("{} {} = 0.0;", property_type, name), format!("{} p_{}", property_type, name)),
+        _ if variant_type == "INT" => (format!("{} {} = 0;", property_type, name), format!("{} p_{}", property_type, name)),
+        _ => (format!("{} {};", property_type, name), format!("const {} &p_{}", property_type, name)),
+    };
+    let declarations = vec![
+        format!("void {}({});", setter, param),
+        format!("{} {}() const;", property_type, getter),
+    ];
+    let definitions = vec![
+        format!("void {}::{}({}) {{", class.name, setter, param),
+        format!("    {} = p_{};", name, name),
+        "}".to_string(),
+        String::new(),
+        format!("{} {}::{}() const {{", property_type, class.name, getter),
+        format!("    return {};", name),
+        "}".to_string(),
+    ];
+    let property_info = format_property_info(variant_type, name, &hint, propertyargs.hint_string.as_deref().unwrap_or_default());
+    let bindings = vec![
+        format!("ClassDB::bind_method(D_METHOD(\"{}\", \"{}\"), &{}::{});", setter, name, class.name, setter),
+        format!("ClassDB::bind_method(D_METHOD(\"{}\"), &{}::{});", getter, class.name, getter),
+        format!("ADD_PROPERTY({}, \"{}\", \"{}\");", property_info, setter, getter),
+    ];
+
+    info!("Adding property '{}' to '{}'", name, class.name);
+    edit_class(ws, &class, ClassEdit { members: vec![member], declarations, definitions, bindings, ..Default::default() })
+}
+
+
+pub fn add_signal(ws: &mut Workspace, path: &Path, signalargs: &AddSignalArgs, src_dir: &str, manifest: Option<&Manifest>) -> Result<(), Error> {
+    let signature = parse_signature(&signalargs.signature)?;
+    let binding = get_signal_binding(&signature)?;
+
+    let class = find_class_files(ws, path, &signalargs.class, src_dir, manifest)?;
+    let source = ws.read_to_string(&class.source).with_context(|| format!("Tried reading '{}'", class.source.display()))?;
+    if source.contains(&format!("MethodInfo(\"{}\"", signature.name)) {
+        return Err(Error::msg(format!("'{}' already has a signal named '{}'.", class.name, signature.name)));
+    };
+
+    // The helper passes its arguments on to 'emit_signal'
+    let (mut declarations, mut definitions) = (vec![], vec![]);
+    if signalargs.emit {
+        let emitter = format!("emit_{}", signature.name);
+        check_method_free(ws, &class, &emitter)?;
+        let params = signature.params.iter().map(|p| header::format_param(p, false)).collect::<Vec<String>>().join(", ");
+        let args = signature.params.iter().map(|p| format!(", {}", p.name)).collect::<String>();
+        declarations.push(format!("void {}({});", emitter, params));
+        definitions.push(format!("void {}::{}({}) {{", class.name, emitter, params));
+        definitions.push(format!("    emit_signal(\"{}\"{});", signature.name, args));
+        definitions.push("}".to_string());
+    };
+
+    info!("Adding signal '{}' to '{}'", signature.name, class.name);
+    edit_class(ws, &class, ClassEdit { declarations, definitions, bindings: vec![binding], ..Default::default() })
+}
+
+
+pub fn add_enum(ws: &mut Workspace, path: &Path, enumargs: &AddEnumArgs, src_dir: &str, manifest: Option<&Manifest>) -> Result<(), Error> {
+    naming::validate_identifier(&enumargs.name, "enum")?;
+    if enumargs.values.is_empty() {
+        return Err(Error::msg(format!("The enum '{}' needs at least one value.", enumargs.name)));
+    };
+    // Values can be given explicitly ('FLAG_A=1')
+    let mut values = vec![];
+    for value in enumargs.values.iter() {
+        let (name, number) = match value.split_once('=') {
+            Some((name, number)) => (name.trim(), Some(parse_integer(number.trim())?)),
+            None => (value.trim(), None),
+        };
+        naming::validate_identifier(name, "enum value")?;
+        if values.iter().any(|(other, _)| *other == name) {
+            return Err(Error::msg(format!("The enum '{}' has the value '{}' twice.", enumargs.name, name)));
+        };
+        values.push((name, number));
+    };
+    if enumargs.bitfield {
+        set_bitfield_flags(&enumargs.name, &mut values)?;
+    };
+
+    let class = find_class_files(ws, path, &enumargs.class, src_dir, manifest)?;
+    let header = ws.read_to_string(&class.header).with_context(|| format!("Tried reading '{}'", class.header.display()))?;
+    if header.contains(&format!("enum {} ", enumargs.name)) || header.contains(&format!("enum {}{{", enumargs.name)) {
+        return Err(Error::msg(format!("'{}' already has an enum named '{}'.", class.name, enumargs.name)));
+    };
+    for (name, _) in &values {
+        check_constant_free(ws, &class, name)?;
+    };
+
+    let mut declarations = vec![String::new(), format!("enum {} {{", enumargs.name)];
+    declarations.extend(values.iter().map(|(name, number)| match number {
+        Some(number) => format!("    {} = {},", name, number),
+        None => format!("    {},", name),
+    }));
+    declarations.push("};".to_string());
+    let version = manifest.map(|m| m.project.godot_version).unwrap_or_default();
+    let (cast, bind) = if enumargs.bitfield { ("VARIANT_BITFIELD_CAST", "BIND_BITFIELD_FLAG") } else { ("VARIANT_ENUM_CAST", "BIND_ENUM_CONSTANT") };
+    let bindings = values.iter().map(|(name, _)| format!("{}({});", bind, name)).collect();
+
+    info!("Adding {} '{}' to '{}'", if enumargs.bitfield { "bitfield" } else { "enum" }, enumargs.name, class.name);
+    edit_class(ws, &class, ClassEdit {
+        declarations,
+        casts: vec![get_enum_cast(cast, &class.name, &enumargs.name, version)],
+        bindings,
+        ..Default::default()
+    })
+}
+
+
+
+/// Flags without a value get the lowest bit none of the other flags use, e.g. 'A=1,B,C=2,D' becomes 'A=1,B=4,C=2,D=8'
+fn set_bitfield_flags(bitfield: &str, values: &mut [(&str, Option<i64>)]) -> Result<(), Error> {
+    let mut used = values.iter().filter_map(|(_, number)| *number).fold(0, |used, number| used | number);
+    for (_, number) in values.iter_mut().filter(|(_, number)| number.is_none()) {
+        let flag = (0..63).map(|bit| 1i64 << bit).find(|flag| used & flag == 0)
+            .with_context(|| format!("The bitfield '{}' has too many flags.", bitfield))?;
+        used |= flag;
+        *number = Some(flag);
+    };
+    Ok(())
+}
+
+/// The line which makes an enum of a class usable as a Variant, e.g. 'VARIANT_ENUM_CAST(Player::State);'
+pub fn get_enum_cast(cast: &str, classname: &str, enum_name: &str, version: GodotVersion) -> String {
+    if version.has_qualified_enum_casts() {
+        format!("{}({}::{});", cast, classname, enum_name)
+    } else {
+        format!("{}({}, {});", cast, classname, enum_name)
+    }
+}
+
+
+pub fn add_constant(ws: &mut Workspace, path: &Path, constantargs: &AddConstantArgs, src_dir: &str, manifest: Option<&Manifest>) -> Result<(), Error> {
+    naming::validate_identifier(&constantargs.name, "constant")?;
+    let value = parse_integer(&constantargs.value)?;
+    let class = find_class_files(ws, path, &constantargs.class, src_dir, manifest)?;
+    check_constant_free(ws, &class, &constantargs.name)?;
+
+    info!("Adding constant '{}' to '{}'", constantargs.name, class.name);
+    edit_class(ws, &class, ClassEdit {
+        declarations: vec![format!("static constexpr int64_t {} = {};", constantargs.name, value)],
+        bindings: vec![format!("BIND_CONSTANT({});", constantargs.name)],
+        ..Default::default()
+    })
+}
+
+
+/// Integer constants are bound as 64 bit ints, hexadecimal values ('0x10') are allowed
+fn parse_integer(value: &str) -> Result<i64, Error> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let parsed = match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse::<i64>(),
+    };
+    parsed.map(|number| if negative { -number } else { number })
+        .with_context(|| format!("'{}' is not an integer.", value))
+}
+
+
+/// Refuses to bind a constant (or enum value) twice
+fn check_constant_free(ws: &Workspace, class: &ClassFiles, name: &str) -> Result<(), Error> {
+    let source = ws.read_to_string(&class.source).with_context(|| format!("Tried reading '{}'", class.source.display()))?;
+    if ["BIND_CONSTANT", "BIND_ENUM_CONSTANT", "BIND_BITFIELD_FLAG"].iter().any(|bind| source.contains(&format!("{}({})", bind, name))) {
+        return Err(Error::msg(format!("'{}' already has a constant named '{}'.", class.name, name)));
+    };
+    Ok(())
+}
+
+
+/// Returns the 'bind_method' (or 'bind_static_method') call of a method, default arguments become 'DEFVAL's
+pub fn get_method_binding(classname: &str, signature: &Signature) -> String {
+    let mut d_method = format!("D_METHOD(\"{}\"", signature.name);
+    for param in &signature.params {
+        d_method.push_str(&format!(", \"{}\"", param.name));
+    };
+    d_method.push(')');
+    let defvals = signature.params.iter()
+        .filter_map(|p| p.default.as_ref().map(|default| format!(", DEFVAL({})", default)))
+        .collect::<String>();
+    if signature.is_static {
+        format!("ClassDB::bind_static_method(\"{}\", {}, &{}::{}{});", classname, d_method, classname, signature.name, defvals)
+    } else {
+        format!("ClassDB::bind_method({}, &{}::{}{});", d_method, classname, signature.name, defvals)
+    }
+}
+
+
+/// Returns the 'ADD_SIGNAL' line of a signal, its arguments keep their types
+pub fn get_signal_binding(signature: &Signature) -> Result<String, Error> {
+    if signature.return_type != "void" || signature.is_const || signature.is_static {
+        return Err(Error::msg(format!("'{}' is a signal, it can't return something or be const or static.", signature.name)));
+    };
+    if let Some(param) = signature.params.iter().find(|p| p.default.is_some()) {
+        return Err(Error::msg(format!("The signal argument '{}' can't have a default value.", param.name)));
+    };
+    let mut method_info = format!("MethodInfo(\"{}\"", signature.name);
+    for param in &signature.params {
+        method_info.push_str(&format!(", {}", get_property_info(&param.param_type, &param.name)?));
+    };
+    method_info.push(')');
+    Ok(format!("ADD_SIGNAL({});", method_info))
+}
+
+
+/// Returns the 'PropertyInfo' of a property, the hint is left out if there is none
+pub fn format_property_info(variant_type: &str, name: &str, hint: &str, hint_string: &str) -> String {
+    let hint_string = hint_string.replace('\\', "\\\\").replace('"', "\\\"");
+    if hint == "PROPERTY_HINT_NONE" && hint_string.is_empty() {
+        format!("PropertyInfo(Variant::{}, \"{}\")", variant_type, name)
+    } else {
+        format!("PropertyInfo(Variant::{}, \"{}\", {}, \"{}\")", variant_type, name, hint, hint_string)
+    }
+}
+
+
+/// Returns the 'PropertyInfo' describing a signal argument. Objects ('Node *', 'Ref<Texture2D>') keep their class name
+fn get_property_info(param_type: &str, name: &str) -> Result<String, Error> {
+    let base_type = param_type.trim_start_matches("const ").trim_end_matches('&').trim();
+    let object_class = base_type.strip_suffix('*')
+        .or(base_type.strip_prefix("Ref<").and_then(|t| t.strip_suffix('>')))
+        .map(|class| class.trim());
+    if let Some(class) = object_class {
+        naming::validate_identifier(class, "class")?;
+        return Ok(format!("PropertyInfo(Variant::OBJECT, \"{}\", PROPERTY_HINT_NONE, \"\", PROPERTY_USAGE_DEFAULT, \"{}\")", name, class));
+    };
+    Ok(match get_variant_type(base_type)? {
+        "NIL" => format!("PropertyInfo(Variant::NIL, \"{}\", PROPERTY_HINT_NONE, \"\", PROPERTY_USAGE_NIL_IS_VARIANT)", name),
+        variant_type => format!("PropertyInfo(Variant::{}, \"{}\")", variant_type, name),
+    })
+}
+
+
+/// Returns the 'Variant::Type' (without the prefix) of a C++ type, e.g. 'FLOAT' for 'float'
+pub fn get_variant_type(type_name: &str) -> Result<&'static str, Error> {
+    VARIANT_TYPES.iter()
+        .find(|(name, _)| *name == type_name)
+        .map(|(_, variant_type)| *variant_type)
+        .with_context(|| format!("'{}' is not a Variant type, use one of: {}.", type_name,
+            VARIANT_TYPES.iter().map(|(name, _)| *name).collect::<Vec<&str>>().join(", ")))
+}
+
+
+/// Turns 'range' or 'PROPERTY_HINT_RANGE' into 'PROPERTY_HINT_RANGE'
+pub fn get_property_hint(hint: &str) -> Result<String, Error> {
+    let upper = hint.to_uppercase();
+    let name = upper.strip_prefix("PROPERTY_HINT_").unwrap_or(&upper);
+    if !PROPERTY_HINTS.contains(&name) {
+        return Err(Error::msg(format!("'{}' is not a property hint, use one of: {}.", hint, PROPERTY_HINTS.map(|h| h.to_lowercase()).join(", "))));
+    };
+    Ok(format!("PROPERTY_HINT_{}", name))
+}
+
+
+/// Adds everything of an edit to a class. Nothing is written unless all of it can be placed
+fn edit_class(ws: &mut Workspace, class: &ClassFiles, edit: ClassEdit) -> Result<(), Error> {
+    let header = ws.read_to_string(&class.header).with_context(|| format!("Tried reading '{}'", class.header.display()))?;
+    let source = ws.read_to_string(&class.source).with_context(|| format!("Tried reading '{}'", class.source.display()))?;
+    // Annotated classes get their '_bind_methods' from 'gdsetup bind'
+    let generated = bind::get_generated_path(&class.header);
+    if !edit.bindings.is_empty() && ws.exists(&generated) && !source.contains(&format!("{}::_bind_methods()", class.name)) {
+        return Err(Error::msg(format!("'{}' is bound by '{}', annotate the declaration in '{}' with '// @gd.*' instead and run 'gdsetup bind'.",
+            class.name, generated.display(), class.header.display())));
+    };
+    let header = insert_into_section(&header, &class.name, "public:", &edit.declarations)
+        .and_then(|header| insert_into_section(&header, &class.name, "private:", &edit.members))
+        .and_then(|header| insert_after_class(&header, &class.name, &edit.casts))
+        .with_context(|| format!("Could not edit '{}'", class.header.display()))?;
+    let source = insert_definitions(&source, &class.name, &edit.definitions);
+    let source = insert_bindings(&source, &class.name, &edit.bindings)
+        .with_context(|| format!("Could not edit '{}'", class.source.display()))?;
+
+    ws.write(&class.header, header).with_context(|| format!("Tried writing '{}'", class.header.display()))?;
+    ws.write(&class.source, source).with_context(|| format!("Tried writing '{}'", class.source.display()))?;
+    Ok(())
+}
+
+
+/// Refuses to define a method twice
+fn check_method_free(ws: &Workspace, class: &ClassFiles, method: &str) -> Result<(), Error> {
+    let source = ws.read_to_string(&class.source).with_context(|| format!("Tried reading '{}'", class.source.display()))?;
+    if source.contains(&format!("{}::{}(", class.name, method)) {
+        return Err(Error::msg(format!("'{}' already has a method named '{}'.", class.name, method)));
+    };
+    Ok(())
+}
+
+
+/// Finds the files of a class by its C++ name ('Player') or file name ('player'). Classes which are not in the manifest
+/// are searched for inside the source folder and the folders of added modules.
+pub fn find_class_files(ws: &Workspace, path: &Path, class: &str, src_dir: &str, manifest: Option<&Manifest>) -> Result<ClassFiles, Error> {
+    let classname = naming::to_pascal_case(class);
+    if let Some(manifest) = manifest {
+        for module in &manifest.modules {
+            if let Some(found) = module.classes.iter().find(|c| c.name == classname || c.name == class || c.file == class) {
+                let folder = path.join(&module.src_dir);
+                return Ok(ClassFiles {
+                    name: found.name.clone(),
+                    header: folder.join(format!("{}.h", found.file)),
+                    source: folder.join(format!("{}.cpp", found.file)),
+                });
+            };
+        };
+    };
+
+    let src_folder = path.join(src_dir);
+    let mut folders = vec![src_folder.clone()];
+    folders.extend(ws.read_dir(&src_folder).unwrap_or_default().into_iter().filter(|entry| ws.is_dir(entry)));
+    for folder in folders {
+        for file in [class.to_string(), naming::to_snake_case(class)] {
+            let header = folder.join(format!("{}.h", file));
+            if !ws.exists(&header) {
+                continue;
+            };
+            // Prefer the name the class registers itself with
+            let contents = ws.read_to_string(&header).unwrap_or_default();
+            let name = header::get_gdclass_name(&contents).unwrap_or(classname);
+            return Ok(ClassFiles { name, header, source: folder.join(format!("{}.cpp", file)) });
+        };
+    };
+    Err(Error::msg(format!("Could not find the class '{}' inside '{}'.", class, src_folder.display())))
+}
+
+
+/// Parses the signature syntax of the command line, e.g. 'get_health() const -> int'
+pub fn parse_signature(signature: &str) -> Result<Signature, Error> {
+    let (left, return_type) = match signature.split_once("->") {
+        Some((left, return_type)) => (left.trim(), return_type.trim()),
+        None => (signature.trim(), "void"),
+    };
+    if return_type.is_empty() {
+        return Err(Error::msg(format!("'{}' is missing the return type after '->'.", signature)));
+    };
+    let (name, params, is_const) = match left.split_once('(') {
+        Some((name, rest)) => {
+            let (params, suffix) = rest.rsplit_once(')').with_context(|| format!("'{}' is missing a closing ')'.", signature))?;
+            let is_const = match suffix.trim() {
+                "" => false,
+                "const" => true,
+                other => return Err(Error::msg(format!("Unexpected '{}' after the parameters of '{}'.", other, signature))),
+            };
+            (name.trim(), params, is_const)
+        },
+        None => (left, "", false),
+    };
+    naming::validate_identifier(name, "method")?;
+
+    let mut parsed = vec![];
+    for param in header::split_params(params) {
+        let param = header::parse_param(&param)?;
+        if param.default.is_none() && parsed.iter().any(|p: &Param| p.default.is_some()) {
+            return Err(Error::msg(format!("'{}' needs a default value, it comes after a parameter with one.", param.name)));
+        };
+        parsed.push(param);
+    };
+    Ok(Signature { name: name.to_string(), params: parsed, return_type: return_type.to_string(), is_const, is_static: false })
+}
+
+
+/// Adds lines to the end of a section ('public:' or 'private:') of a class declaration. A missing section is added
+/// to the end of the declaration
+fn insert_into_section(header: &str, classname: &str, section: &str, additions: &[String]) -> Result<String, Error> {
+    if additions.is_empty() {
+        return Ok(header.to_string());
+    };
+    let mut lines = header.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    let start = lines.iter().position(|l| header::is_class_declaration(l, classname))
+        .with_context(|| format!("There is no declaration of the class '{}'", classname))?;
+    let end = header::find_class_end(&lines, start).with_context(|| format!("The declaration of '{}' has no end", classname))?;
+    let mut additions = additions.iter().map(|addition| indent(addition)).collect::<Vec<String>>();
+    let mut insert_at = match (start..end).rfind(|idx| lines[*idx].trim() == section) {
+        Some(label) => (label + 1..end).find(|idx| matches!(lines[*idx].trim(), "public:" | "private:" | "protected:")).unwrap_or(end),
+        None => {
+            additions.insert(0, String::new());
+            additions.insert(1, section.to_string());
+            end
+        },
+    };
+    while insert_at > start + 1 && lines[insert_at - 1].trim().is_empty() {
+        insert_at -= 1;
+    };
+    lines.splice(insert_at..insert_at, additions);
+    Ok(regions::join_lines(lines, header))
+}
+
+
+/// Adds lines behind a class declaration, after the ones added before
+fn insert_after_class(header: &str, classname: &str, additions: &[String]) -> Result<String, Error> {
+    if additions.is_empty() {
+        return Ok(header.to_string());
+    };
+    let mut lines = header.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    let start = lines.iter().position(|l| header::is_class_declaration(l, classname))
+        .with_context(|| format!("There is no declaration of the class '{}'", classname))?;
+    let end = header::find_class_end(&lines, start).with_context(|| format!("The declaration of '{}' has no end", classname))?;
+    let mut insert_at = end + 1;
+    for (idx, line) in lines.iter().enumerate().skip(end + 1) {
+        match line.trim() {
+            "" => continue,
+            line if line.starts_with("VARIANT_ENUM_CAST(") || line.starts_with("VARIANT_BITFIELD_CAST(") => insert_at = idx + 1,
+            _ => break,
+        };
+    };
+    let mut additions = additions.to_vec();
+    if insert_at == end + 1 {
+        additions.insert(0, String::new());
+    };
+    lines.splice(insert_at..insert_at, additions);
+    Ok(regions::join_lines(lines, header))
+}
+
+
+/// Indents a line of a class body, empty lines stay empty
+fn indent(line: &str) -> String {
+    if line.is_empty() { String::new() } else { format!("    {}", line) }
+}
+
+
+/// Adds a definition in front of '_bind_methods' (or to the end of the file)
+fn insert_definitions(source: &str, classname: &str, definition: &[String]) -> String {
+    if definition.is_empty() {
+        return source.to_string();
+    };
+    let mut lines = source.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    match lines.iter().position(|l| l.contains(&format!("{}::_bind_methods()", classname))) {
+        Some(idx) => {
+            let mut block = definition.to_vec();
+            block.push(String::new());
+            lines.splice(idx..idx, block);
+        },
+        None => {
+            lines.push(String::new());
+            lines.extend(definition.iter().cloned());
+        },
+    };
+    regions::join_lines(lines, source)
+}
+
+
+/// Adds lines to the end of the '_bind_methods' definition
+fn insert_bindings(source: &str, classname: &str, bindings: &[String]) -> Result<String, Error> {
+    let mut lines = source.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    let start = lines.iter().position(|l| l.contains(&format!("{}::_bind_methods()", classname)))
+        .with_context(|| format!("There is no definition of '{}::_bind_methods'", classname))?;
+    let end = (start..lines.len()).find(|idx| lines[*idx].starts_with('}'))
+        .with_context(|| format!("The definition of '{}::_bind_methods' has no end", classname))?;
+    for (i, binding) in bindings.iter().enumerate() {
+        lines.insert(end + i, indent(binding));
+    };
+    Ok(regions::join_lines(lines, source))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_of_the_command_line() {
+        let signature = parse_signature("get_health() const -> int").unwrap();
+        assert_eq!((signature.name.as_str(), signature.return_type.as_str()), ("get_health", "int"));
+        assert!(signature.is_const && signature.params.is_empty());
+
+        let signature = parse_signature("set_speed").unwrap();
+        assert_eq!(signature.return_type, "void");
+        assert!(!signature.is_const && signature.params.is_empty());
+
+        let signature = parse_signature("set_items(const TypedArray<Node> &items, Dictionary<String, int> map = {}) -> Ref<Resource>").unwrap();
+        assert_eq!(signature.return_type, "Ref<Resource>");
+        assert_eq!(signature.params.len(), 2);
+        assert_eq!(signature.params[1].param_type, "Dictionary<String, int>");
+        assert_eq!(signature.params[1].default.as_deref(), Some("{}"));
+    }
+
+    #[test]
+    fn invalid_signatures() {
+        assert!(parse_signature("get_health() ->").is_err());
+        assert!(parse_signature("get_health(").is_err());
+        assert!(parse_signature("get_health() static").is_err());
+        assert!(parse_signature("2fast()").is_err());
+        assert!(parse_signature("move(float speed = 1.0, Vector2 direction)").is_err());
+    }
+
+    #[test]
+    fn implicit_flags_skip_the_explicit_ones() {
+        let mut values = vec![("A", None), ("B", Some(1)), ("C", None), ("D", Some(4)), ("E", None)];
+        set_bitfield_flags("Flags", &mut values).unwrap();
+        assert_eq!(values, vec![("A", Some(2)), ("B", Some(1)), ("C", Some(8)), ("D", Some(4)), ("E", Some(16))]);
+
+        let mut values = vec![("ALL", Some(7)), ("NEXT", None)];
+        set_bitfield_flags("Flags", &mut values).unwrap();
+        assert_eq!(values[1], ("NEXT", Some(8)));
+        assert!(set_bitfield_flags("Flags", &mut [("ALL", Some(-1)), ("MORE", None)]).is_err());
+    }
+}
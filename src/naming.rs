@@ -0,0 +1,187 @@
+use anyhow::{Result, Error};
+
+
+/// Reserved words of C++ (up to C++20), none of them can name a module or a class
+const CPP_KEYWORDS: [&str; 92] = [
+    "alignas", "alignof", "and", "and_eq", "asm", "auto", "bitand", "bitor", "bool", "break",
+    "case", "catch", "char", "char8_t", "char16_t", "char32_t", "class", "compl", "concept", "const",
+    "consteval", "constexpr", "constinit", "const_cast", "continue", "co_await", "co_return", "co_yield", "decltype", "default",
+    "delete", "do", "double", "dynamic_cast", "else", "enum", "explicit", "export", "extern", "false",
+    "float", "for", "friend", "goto", "if", "inline", "int", "long", "mutable", "namespace",
+    "new", "noexcept", "not", "not_eq", "nullptr", "operator", "or", "or_eq", "private", "protected",
+    "public", "register", "reinterpret_cast", "requires", "return", "short", "signed", "sizeof", "static", "static_assert",
+    "static_cast", "struct", "switch", "template", "this", "thread_local", "throw", "true", "try", "typedef",
+    "typeid", "typename", "union", "unsigned", "using", "virtual", "void", "volatile", "wchar_t", "while",
+    "xor", "xor_eq",
+];
+
+/// File names of generated files a class would overwrite
+const RESERVED_FILES: [&str; 1] = ["register_types"];
+
+
+/// Converts a name into snake_case (e.g. 'MyCoolNode' -> 'my_cool_node', 'HTTPServer3D' -> 'http_server3d')
+pub fn to_snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<char>>();
+    let mut snake = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).map(|n| n.is_lowercase()).unwrap_or(false);
+            if prev.is_lowercase() || (prev.is_uppercase() && next_is_lower) {
+                snake.push('_');
+            };
+        };
+        snake.extend(c.to_lowercase());
+    };
+    snake
+}
+
+
+/// Converts a name into PascalCase (e.g. 'my_cool_node' -> 'MyCoolNode'). Names which already are PascalCase stay the same
+pub fn to_pascal_case(name: &str) -> String {
+    let mut pascal = String::new();
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            pascal.extend(first.to_uppercase());
+            pascal.push_str(chars.as_str());
+        };
+    };
+    pascal
+}
+
+
+/// Converts a name into SCREAMING_SNAKE_CASE (e.g. 'my_cool_node' -> 'MY_COOL_NODE'), used for header guards
+pub fn to_screaming_snake_case(name: &str) -> String {
+    to_snake_case(name).to_uppercase()
+}
+
+
+/// Checks that a name can be used as a C++ identifier, 'kind' (e.g. 'method') is used in the error messages
+pub fn validate_identifier(name: &str, kind: &str) -> Result<(), Error> {
+    let Some(first) = name.chars().next() else {
+        return Err(Error::msg(format!("The {} name can't be empty.", kind)));
+    };
+    if let Some(invalid) = name.chars().find(|c| !c.is_ascii_alphanumeric() && *c != '_') {
+        return Err(Error::msg(format!("'{}' is not a valid {} name: '{}' is not allowed, use only letters, digits and '_'.", name, kind, invalid)));
+    };
+    if !first.is_ascii_alphabetic() && first != '_' {
+        return Err(Error::msg(format!("'{}' is not a valid {} name: it has to start with a letter or '_'.", name, kind)));
+    };
+    if name.starts_with("__") || (first == '_' && name.chars().nth(1).is_some_and(|c| c.is_ascii_uppercase())) {
+        return Err(Error::msg(format!("'{}' is not a valid {} name: names starting with '__' or '_' and an uppercase letter are reserved in C++.", name, kind)));
+    };
+    if !name.chars().any(|c| c.is_ascii_alphabetic()) {
+        return Err(Error::msg(format!("'{}' is not a valid {} name: it needs at least one letter.", name, kind)));
+    };
+    if CPP_KEYWORDS.contains(&name) {
+        return Err(Error::msg(format!("'{}' is not a valid {} name: it is a C++ keyword.", name, kind)));
+    };
+    Ok(())
+}
+
+
+/// Checks the name of a module, it prefixes the entry symbol, the library and the initialization functions
+pub fn validate_module_name(name: &str) -> Result<(), Error> {
+    validate_identifier(name, "module")
+}
+
+
+/// Checks the name of a class. It names the .h/.cpp files and (as PascalCase) the C++ class.
+/// Collisions with Godot classes are checked by 'ExtensionApi::check_class_name'
+pub fn validate_class_name(name: &str) -> Result<(), Error> {
+    validate_identifier(name, "class")?;
+    if RESERVED_FILES.contains(&name) {
+        return Err(Error::msg(format!("'{}' is not a valid class name: gdsetup already generates '{}.h' and '{}.cpp'.", name, name, name)));
+    };
+    let classname = to_pascal_case(name);
+    if CPP_KEYWORDS.contains(&classname.as_str()) {
+        return Err(Error::msg(format!("'{}' is not a valid class name: '{}' is a C++ keyword.", name, classname)));
+    };
+    Ok(())
+}
+
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+
+/// Replaces 'old' with 'new' where it is not part of a longer identifier, e.g. 'Player::' but not 'MyPlayer::' and
+/// 'libgdgame' but not 'libgdgame2'. Only the ends of 'old' which are identifier characters need a boundary
+pub fn replace_identifier(text: &str, old: &str, new: &str) -> String {
+    if old.is_empty() {
+        return text.to_string();
+    };
+    let check_start = old.starts_with(is_identifier_char);
+    let check_end = old.ends_with(is_identifier_char);
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+    for (idx, _) in text.match_indices(old) {
+        let before = text[..idx].chars().next_back();
+        let after = text[idx + old.len()..].chars().next();
+        if (check_start && before.is_some_and(is_identifier_char)) || (check_end && after.is_some_and(is_identifier_char)) {
+            continue;
+        };
+        replaced.push_str(&text[last..idx]);
+        replaced.push_str(new);
+        last = idx + old.len();
+    };
+    replaced.push_str(&text[last..]);
+    replaced
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snake_case_splits_words_and_acronyms() {
+        assert_eq!(to_snake_case("MyCoolNode"), "my_cool_node");
+        assert_eq!(to_snake_case("HTTPServer3D"), "http_server3d");
+        assert_eq!(to_snake_case("CharacterBody3D"), "character_body3d");
+        assert_eq!(to_snake_case("already_snake"), "already_snake");
+        assert_eq!(to_snake_case("AESContext"), "aes_context");
+    }
+
+    #[test]
+    fn pascal_case_joins_words() {
+        assert_eq!(to_pascal_case("my_cool_node"), "MyCoolNode");
+        assert_eq!(to_pascal_case("MyCoolNode"), "MyCoolNode");
+        assert_eq!(to_pascal_case("__double__underscore_"), "DoubleUnderscore");
+        assert_eq!(to_pascal_case("node3d"), "Node3d");
+        assert_eq!(to_screaming_snake_case("my_cool_node"), "MY_COOL_NODE");
+    }
+
+    #[test]
+    fn identifiers_have_to_be_valid_cpp() {
+        assert!(validate_identifier("my_node2", "class").is_ok());
+        assert!(validate_identifier("_private", "method").is_ok());
+        assert!(validate_identifier("", "class").is_err());
+        assert!(validate_identifier("2d_node", "class").is_err());
+        assert!(validate_identifier("my-node", "class").is_err());
+        assert!(validate_identifier("__reserved", "class").is_err());
+        assert!(validate_identifier("_Reserved", "class").is_err());
+        assert!(validate_identifier("_1", "class").is_err());
+        assert!(validate_identifier("namespace", "module").is_err());
+    }
+
+    #[test]
+    fn class_names_can_not_collide_with_generated_files_or_keywords() {
+        assert!(validate_class_name("player").is_ok());
+        assert!(validate_class_name("register_types").is_err());
+        assert!(validate_class_name("class").is_err());
+        assert!(validate_class_name("this_class").is_ok());
+        assert!(validate_module_name("my_game").is_ok());
+    }
+
+    #[test]
+    fn replaces_whole_identifiers_only() {
+        assert_eq!(replace_identifier("Player::a(); MyPlayer::b(); Player2::c();", "Player::", "Hero::"), "Hero::a(); MyPlayer::b(); Player2::c();");
+        assert_eq!(replace_identifier("libgdgame{} libgdgame2{}", "libgdgame", "libgdmod"), "libgdmod{} libgdgame2{}");
+        assert_eq!(replace_identifier("target_link_libraries(game\ntarget_link_libraries(game2 x)", "target_link_libraries(game", "target_link_libraries(mod"), "target_link_libraries(mod\ntarget_link_libraries(game2 x)");
+        assert_eq!(replace_identifier("#include \"a.h\"", "\"a.h\"", "\"b.h\""), "#include \"b.h\"");
+        assert_eq!(replace_identifier("abc", "", "x"), "abc");
+    }
+}
@@ -0,0 +1,282 @@
+use std::{collections::BTreeMap, io::Write, path::{Component, Path, PathBuf}, process::Command};
+
+use anyhow::{Result, Error, Context};
+use log::{info, debug, warn};
+use minijinja::Value;
+use serde::Deserialize;
+
+use crate::{templates::{self, TEMPLATES_DIR}, workspace::Workspace};
+
+
+/// Name of the manifest file inside a template pack
+pub const PACK_MANIFEST: &str = "gdsetup-template.toml";
+/// Folder inside a template pack whose files replace the built-in templates
+const PACK_TEMPLATES_DIR: &str = "templates";
+
+
+/// Describes a template pack, a directory (or git repository) used as the starting point of new projects
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PackManifest {
+    #[serde(default)]
+    pub template: PackInfo,
+    #[serde(default, rename = "variable")]
+    pub variables: Vec<PackVariable>,
+    #[serde(default, rename = "file")]
+    pub files: Vec<PackFile>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PackInfo {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Folder which contains the Godot project, replaces the default 'godot'
+    pub godot_dir: Option<String>,
+    /// Folder which contains the C++ sources, replaces the default 'src'
+    pub src_dir: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PackVariable {
+    pub name: String,
+    /// Question asked on the command line. Variables without a prompt just use their default
+    pub prompt: Option<String>,
+    pub default: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PackFile {
+    /// File or folder (relative to the pack)
+    pub source: String,
+    /// Where to put it (relative to the project root), can use variables. Defaults to 'source'
+    pub target: Option<String>,
+    /// Whether the contents are rendered as a template or copied as they are
+    #[serde(default = "default_render")]
+    pub render: bool,
+}
+
+fn default_render() -> bool {
+    true
+}
+
+
+/// A template pack which is ready to be used. Cloned packs are deleted again once this is dropped.
+#[derive(Debug)]
+pub struct TemplatePack {
+    pub dir: PathBuf,
+    pub manifest: PackManifest,
+    cloned: bool,
+}
+
+
+/// Whether the template argument points to a git repository instead of a local folder
+fn is_git_url(source: &str) -> bool {
+    ["http://", "https://", "ssh://", "git://", "file://", "git@"].iter().any(|prefix| source.starts_with(prefix))
+        || (source.ends_with(".git") && !Path::new(source).exists())
+}
+
+
+impl TemplatePack {
+    /// Opens a template pack from a local folder or clones it from a git repository
+    pub fn fetch(source: &str) -> Result<TemplatePack, Error> {
+        let (dir, cloned) = if is_git_url(source) {
+            let dir = std::env::temp_dir().join(format!("gdsetup-template-{}", std::process::id()));
+            if dir.exists() {
+                std::fs::remove_dir_all(&dir).with_context(|| format!("Tried removing '{}'", dir.display()))?;
+            };
+            info!("Cloning template pack '{}'", source);
+            let output = Command::new("git").args(["clone", "--depth", "1", source]).arg(&dir)
+                .output().with_context(|| "Tried to find git")?;
+            if !output.status.success() {
+                return Err(Error::msg(format!("Could not clone '{}': {}", source, String::from_utf8_lossy(&output.stderr).trim())));
+            };
+            (dir, true)
+        } else {
+            let dir = PathBuf::from(source);
+            if !dir.is_dir() {
+                return Err(Error::msg(format!("Could not find the template pack '{}'.", source)));
+            };
+            (dir, false)
+        };
+
+        // Dropping the pack removes the clone again if it turns out to be invalid
+        let mut pack = TemplatePack { dir, manifest: PackManifest::default(), cloned };
+        let manifest_path = pack.dir.join(PACK_MANIFEST);
+        let contents = std::fs::read_to_string(&manifest_path)
+            .with_context(|| format!("'{}' is not a template pack, it is missing a {}", source, PACK_MANIFEST))?;
+        pack.manifest = toml::from_str(&contents).with_context(|| format!("Tried parsing '{}'", manifest_path.display()))?;
+        if let Some(name) = &pack.manifest.template.name {
+            info!("Using template pack '{}'", name);
+        };
+        Ok(pack)
+    }
+
+    /// Resolves the variables of the pack. Values given on the command line ('NAME=VALUE') win, the others are asked for.
+    pub fn get_variables(&self, given: &[String]) -> Result<BTreeMap<String, String>, Error> {
+        let mut given_values = BTreeMap::new();
+        for var in given {
+            let (name, value) = var.split_once('=').with_context(|| format!("'{}' is not a variable, use NAME=VALUE", var))?;
+            given_values.insert(name.trim().to_string(), value.to_string());
+        };
+
+        let mut variables = BTreeMap::new();
+        for variable in &self.manifest.variables {
+            let value = match (given_values.remove(&variable.name), &variable.prompt) {
+                (Some(value), _) => value,
+                (None, Some(prompt)) => ask(prompt, variable.default.as_deref())?,
+                (None, None) => variable.default.clone().unwrap_or_default(),
+            };
+            variables.insert(variable.name.clone(), value);
+        };
+        for name in given_values.keys() {
+            warn!("The template pack does not use the variable '{}'", name);
+        };
+        Ok(variables)
+    }
+
+    /// Copies the template overrides of the pack into the project, so that later commands keep using them.
+    /// Templates the project already has are kept.
+    pub fn install_templates(&self, ws: &mut Workspace, root: &Path) -> Result<(), Error> {
+        let pack_templates = self.dir.join(PACK_TEMPLATES_DIR);
+        if !pack_templates.is_dir() {
+            return Ok(());
+        };
+        let project_templates = root.join(TEMPLATES_DIR);
+        for (name, _) in templates::BUILTIN_TEMPLATES {
+            let source = pack_templates.join(name);
+            let target = project_templates.join(name);
+            if !source.is_file() || ws.exists(&target) {
+                continue;
+            };
+            ws.create_dir_all(&project_templates)?;
+            let contents = std::fs::read(&source).with_context(|| format!("Tried reading '{}'", source.display()))?;
+            ws.write(&target, contents).with_context(|| format!("Tried writing '{}'", target.display()))?;
+        };
+        Ok(())
+    }
+
+    /// Renders (or copies) every file of the pack into the project
+    pub fn render_files(&self, ws: &mut Workspace, root: &Path, context: Value) -> Result<(), Error> {
+        for file in &self.manifest.files {
+            check_relative(&file.source, "source")?;
+            let source = self.dir.join(&file.source);
+            let target = templates::render_str(&file.source, file.target.as_deref().unwrap_or(&file.source), context.clone())?;
+            check_relative(&target, "target")?;
+            if source.is_dir() {
+                self.render_dir(ws, &source, &root.join(target), file.render, &context)?;
+            } else {
+                self.render_file(ws, &source, &root.join(target), file.render, &context)?;
+            };
+        };
+        Ok(())
+    }
+
+    fn render_dir(&self, ws: &mut Workspace, source: &Path, target: &Path, render: bool, context: &Value) -> Result<(), Error> {
+        let entries = std::fs::read_dir(source).with_context(|| format!("Tried reading '{}'", source.display()))?;
+        for entry in entries {
+            let entry = entry?.path();
+            let name = entry.file_name().unwrap();
+            if entry.is_dir() {
+                self.render_dir(ws, &entry, &target.join(name), render, context)?;
+            } else {
+                self.render_file(ws, &entry, &target.join(name), render, context)?;
+            };
+        };
+        Ok(())
+    }
+
+    fn render_file(&self, ws: &mut Workspace, source: &Path, target: &Path, render: bool, context: &Value) -> Result<(), Error> {
+        let name = source.strip_prefix(&self.dir).unwrap_or(source).display().to_string();
+        let contents = if render {
+            let template = std::fs::read_to_string(source).with_context(|| format!("Tried reading '{}'", source.display()))?;
+            templates::render_str(&name, &template, context.clone())?.into_bytes()
+        } else {
+            std::fs::read(source).with_context(|| format!("Tried reading '{}'", source.display()))?
+        };
+        if let Some(parent) = target.parent() {
+            ws.create_dir_all(parent)?;
+        };
+        debug!("Creating '{}' from the template pack", target.display());
+        ws.write(target, contents).with_context(|| format!("Tried writing '{}'", target.display()))
+    }
+}
+
+
+/// Files of a pack have to come from inside the pack and end up inside the project, 'kind' names the path in the error
+fn check_relative(path: &str, kind: &str) -> Result<(), Error> {
+    let relative = Path::new(path);
+    if path.trim().is_empty() || relative.is_absolute() || relative.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(Error::msg(format!("The {} '{}' of the template pack has to be a relative path without '..'.", kind, path)));
+    };
+    Ok(())
+}
+
+
+impl Drop for TemplatePack {
+    fn drop(&mut self) {
+        if self.cloned {
+            _ = std::fs::remove_dir_all(&self.dir);
+        };
+    }
+}
+
+
+/// Asks for a value on the command line, an empty answer uses the default
+fn ask(prompt: &str, default: Option<&str>) -> Result<String, Error> {
+    match default {
+        Some(default) => print!("{} [{}] ", prompt, default),
+        None => print!("{} ", prompt),
+    };
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).with_context(|| "Tried reading the answer")?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Ok(default.unwrap_or_default().to_string());
+    };
+    Ok(answer.to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minijinja::context;
+
+    #[test]
+    fn pack_paths_stay_inside() {
+        for path in ["README.md", "docs/setup.md", "./godot/icon.svg"] {
+            assert!(check_relative(path, "target").is_ok(), "{}", path);
+        };
+        for path in ["", "/etc/passwd", "../outside.txt", "docs/../../outside.txt", "docs/.."] {
+            assert!(check_relative(path, "target").is_err(), "{}", path);
+        };
+    }
+
+    #[test]
+    fn rendered_targets_cant_leave_the_project() {
+        let dir = std::env::temp_dir().join(format!("gdsetup-test-pack-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("README.md"), "# {{ project_name }}").unwrap();
+        let manifest = toml::from_str(r#"
+[[file]]
+source = "README.md"
+target = "{{ folder }}/README.md"
+"#).unwrap();
+        let pack = TemplatePack { dir: dir.clone(), manifest, cloned: false };
+        let root = dir.join("project");
+
+        let mut ws = Workspace::new(true);
+        pack.render_files(&mut ws, &root, context! { project_name => "game", folder => "docs" }).unwrap();
+        assert_eq!(ws.read_to_string(&root.join("docs/README.md")).unwrap(), "# game");
+
+        // A variable must not move the file out of the project
+        let mut ws = Workspace::new(true);
+        assert!(pack.render_files(&mut ws, &root, context! { project_name => "game", folder => "../.." }).is_err());
+        assert!(ws.operations().is_empty());
+
+        let absolute = TemplatePack { dir: dir.clone(), manifest: toml::from_str("[[file]]\nsource = \"/etc/hostname\"\n").unwrap(), cloned: false };
+        assert!(absolute.render_files(&mut ws, &root, context! {}).is_err());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::{Result, Error, Context};
+
+use crate::naming;
+
+
+/// Starts a region of generated lines, followed by the name of the region (e.g. '// gdsetup:begin registrations').
+/// C++ files use '//' comments, SConstruct and CMakeLists.txt '#' comments
+pub const BEGIN_MARKER: &str = "gdsetup:begin";
+/// Ends the innermost open region
+pub const END_MARKER: &str = "gdsetup:end";
+
+
+/// Returns the text of a marker line without its comment characters, e.g. 'gdsetup:begin includes'
+fn get_marker(line: &str) -> Option<&str> {
+    let trimmed = line.trim();
+    let text = trimmed.strip_prefix("//").or(trimmed.strip_prefix('#'))?.trim();
+    (text.starts_with(BEGIN_MARKER) || text == END_MARKER).then_some(text)
+}
+
+
+fn is_begin(line: &str, name: &str) -> bool {
+    get_marker(line).and_then(|marker| marker.strip_prefix(BEGIN_MARKER)).is_some_and(|rest| rest.trim() == name)
+}
+
+
+/// Returns the line indices of the begin and end markers of region 'name'. Regions can contain other regions
+pub fn find_region(contents: &str, name: &str) -> Option<(usize, usize)> {
+    let lines = contents.lines().collect::<Vec<&str>>();
+    let start = lines.iter().position(|l| is_begin(l, name))?;
+    let mut depth = 0;
+    for (idx, line) in lines.iter().enumerate().skip(start + 1) {
+        match get_marker(line) {
+            Some(END_MARKER) if depth == 0 => return Some((start, idx)),
+            Some(END_MARKER) => depth -= 1,
+            Some(_) => depth += 1,
+            None => (),
+        };
+    };
+    None
+}
+
+
+/// Returns the lines inside of region 'name'
+pub fn read_region(contents: &str, name: &str) -> Option<Vec<String>> {
+    let (start, end) = find_region(contents, name)?;
+    Some(contents.lines().skip(start + 1).take(end - start - 1).map(|l| l.to_string()).collect())
+}
+
+
+/// Replaces everything inside of region 'name'. The new lines are indented like the begin marker
+pub fn replace_region(contents: &str, name: &str, replacement: &[String]) -> Result<String, Error> {
+    let (start, end) = find_region(contents, name)
+        .with_context(|| format!("Could not find the region '{} {}' ... '{}'", BEGIN_MARKER, name, END_MARKER))?;
+    let mut lines = contents.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    let indent = lines[start].chars().take_while(|c| c.is_whitespace()).collect::<String>();
+    let replacement = replacement.iter()
+        .map(|line| if line.is_empty() { String::new() } else { format!("{}{}", indent, line) })
+        .collect::<Vec<String>>();
+    lines.splice(start + 1..end, replacement);
+    Ok(join_lines(lines, contents))
+}
+
+
+/// Renames a region, e.g. 'module old' to 'module new'
+pub fn rename_region(contents: &str, old_name: &str, new_name: &str) -> String {
+    let lines = contents.lines()
+        .map(|line| if is_begin(line, old_name) { line.replacen(old_name, new_name, 1) } else { line.to_string() })
+        .collect::<Vec<String>>();
+    join_lines(lines, contents)
+}
+
+
+/// Applies the replacements (of whole identifiers, see [naming::replace_identifier]) to the lines inside of the regions 'names' (and the regions nested in them) only. Everything
+/// outside of them belongs to the user or to other generated code, like the targets of other modules
+pub fn replace_in_regions(contents: &str, names: &[&str], replacements: &[(String, String)]) -> String {
+    let ranges = names.iter().filter_map(|name| find_region(contents, name)).collect::<Vec<(usize, usize)>>();
+    let lines = contents.lines().enumerate()
+        .map(|(idx, line)| {
+            let in_region = get_marker(line).is_none() && ranges.iter().any(|(start, end)| idx > *start && idx < *end);
+            if !in_region {
+                return line.to_string();
+            };
+            replacements.iter().fold(line.to_string(), |line, (old_value, new_value)| naming::replace_identifier(&line, old_value, new_value))
+        })
+        .collect::<Vec<String>>();
+    join_lines(lines, contents)
+}
+
+
+/// Returns for every line whether it is inside of a region. Marker lines are not
+pub fn region_mask(contents: &str) -> Vec<bool> {
+    let mut depth = 0;
+    contents.lines()
+        .map(|line| match get_marker(line) {
+            Some(END_MARKER) => {
+                depth = (depth - 1).max(0);
+                false
+            },
+            Some(_) => {
+                depth += 1;
+                false
+            },
+            None => depth > 0,
+        })
+        .collect()
+}
+
+
+/// Wraps the lines 'first' to 'last' (inclusive) into the markers of region 'name', using 'comment' (e.g. '//')
+pub fn wrap_region(contents: &str, name: &str, comment: &str, first: usize, last: usize) -> String {
+    let mut lines = contents.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    let indent = lines[first].chars().take_while(|c| c.is_whitespace()).collect::<String>();
+    lines.insert(last + 1, format!("{}{} {}", indent, comment, END_MARKER));
+    lines.insert(first, format!("{}{} {} {}", indent, comment, BEGIN_MARKER, name));
+    join_lines(lines, contents)
+}
+
+
+/// Joins edited lines again, keeping the trailing newline of the original
+pub(crate) fn join_lines(lines: Vec<String>, original: &str) -> String {
+    let mut contents = lines.join("\n");
+    if original.ends_with('\n') {
+        contents.push('\n');
+    };
+    contents
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NESTED: &str = "\
+// gdsetup:begin module
+void init() {
+    // gdsetup:begin registrations
+    ClassDB::register_class<Old>();
+    // gdsetup:end
+}
+// gdsetup:end
+user code Old
+";
+
+    #[test]
+    fn finds_nested_regions() {
+        assert_eq!(find_region(NESTED, "module"), Some((0, 6)));
+        assert_eq!(find_region(NESTED, "registrations"), Some((2, 4)));
+        assert_eq!(read_region(NESTED, "registrations").unwrap(), vec!["    ClassDB::register_class<Old>();"]);
+        assert_eq!(find_region(NESTED, "includes"), None);
+    }
+
+    #[test]
+    fn regions_need_an_end_marker() {
+        let contents = "# gdsetup:begin sources\nsources = []\n# gdsetup:begin doc_data\n# gdsetup:end\n";
+        assert_eq!(find_region(contents, "doc_data"), Some((2, 3)));
+        assert_eq!(find_region(contents, "sources"), None);
+        assert!(replace_region(contents, "sources", &["sources = Glob()".to_string()]).is_err());
+    }
+
+    #[test]
+    fn replaced_lines_are_indented_like_the_marker() {
+        let replaced = replace_region(NESTED, "registrations", &["A();".to_string(), String::new(), "B();".to_string()]).unwrap();
+        assert!(replaced.contains("    // gdsetup:begin registrations\n    A();\n\n    B();\n    // gdsetup:end\n"));
+        assert!(replaced.ends_with("user code Old\n"));
+        assert_eq!(find_region(&replaced, "module"), Some((0, 8)));
+    }
+
+    #[test]
+    fn replacements_only_touch_regions() {
+        let replacements = [("Old".to_string(), "New".to_string())];
+        let replaced = replace_in_regions(NESTED, &["module"], &replacements);
+        assert!(replaced.contains("register_class<New>"));
+        assert!(replaced.ends_with("user code Old\n"));
+        // Only the named regions change
+        let other = "// gdsetup:begin a\nOld\n// gdsetup:end\n// gdsetup:begin b\nOld\n// gdsetup:end\n";
+        assert_eq!(replace_in_regions(other, &["b"], &replacements), "// gdsetup:begin a\nOld\n// gdsetup:end\n// gdsetup:begin b\nNew\n// gdsetup:end\n");
+        assert_eq!(region_mask(NESTED), vec![false, true, false, true, false, true, false, false]);
+    }
+
+    #[test]
+    fn renames_and_wraps_regions() {
+        let renamed = rename_region("# gdsetup:begin module old\n# gdsetup:end", "module old", "module new");
+        assert_eq!(renamed, "# gdsetup:begin module new\n# gdsetup:end");
+        let wrapped = wrap_region("a\n    b\n    c\nd\n", "body", "//", 1, 2);
+        assert_eq!(wrapped, "a\n    // gdsetup:begin body\n    b\n    c\n    // gdsetup:end\nd\n");
+    }
+}
@@ -1,170 +1,453 @@
-use std::{path::PathBuf, fmt::format, process::Command};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, Error, Context};
 use log::{info, warn};
 
-use crate::{RenameArgs, codegen, get_basecommand, print_output};
+use crate::{RenameArgs, api::ExtensionApi, bind, header, naming, print_output, manifest::Manifest, regions, workspace::Workspace};
 
 
-fn rename_file_contents(filepath: PathBuf, replacements: Vec<(String, String)>) {
-    let filename = filepath.file_name().unwrap().to_str().unwrap().to_string();
+/// Applies the replacements to a file, longer identifiers which start with an old name stay as they are. Generated files
+/// only change inside of 'regions', if they have all of them. Files of projects generated before the markers existed
+/// (and files without 'regions') are replaced as a whole.
+fn rename_file_contents(ws: &mut Workspace, filepath: PathBuf, replacements: Vec<(String, String)>, regions: &[&str]) -> Result<(), Error> {
+    let original = ws.read_to_string(&filepath).with_context(|| format!("Tried renaming the contents of '{}'", filepath.display()))?;
+    let contents = if !regions.is_empty() && regions.iter().all(|name| regions::find_region(&original, name).is_some()) {
+        regions::replace_in_regions(&original, regions, &replacements)
+    } else {
+        replacements.iter().fold(original, |contents, (old_value, new_value)| naming::replace_identifier(&contents, old_value, new_value))
+    };
+    ws.write(&filepath, contents).with_context(|| format!("Tried renaming the contents of '{}'", filepath.display()))?;
+    info!("Successfully renamed contents of {}", filepath.display());
+    Ok(())
+}
 
-    match std::fs::read_to_string(filepath.clone()) {
-        Ok(val) => {
-            let mut contents = val;
-            for (old_value, new_value) in replacements {
-                contents = contents.replace(&old_value, &new_value);
-            };
-            let write = std::fs::write(filepath, contents);
-            match write {
-                Ok(_) => {
-                    info!("Successfully renamed contents of {}", filename);
-                },
-                Err(err) => {
-                    warn!("An error occured while renaming contents of {}: {}", filename, err);
-                },
-            }
-        },
-        Err(err) => {
-            warn!("An error occured while reading {}: {}", filename, err);
-        }
-    }
+
+pub fn rename(ws: &mut Workspace, renameargs: RenameArgs, godot_dir: &str, src_dir: &str) -> Result<(), Error> {
+    let path = match renameargs.path {
+        Some(path) => path,
+        None => std::env::current_dir().with_context(|| "Getting current directory.")?,
+    };
+
+    ws.set_root(&path);
+    // Prefer the folders recorded in the manifest over the defaults
+    let mut manifest = Manifest::load(ws, &path)?;
+    let (godot_dir, src_dir) = match &manifest {
+        Some(manifest) => (manifest.project.godot_dir.clone(), manifest.project.src_dir.clone()),
+        None => (godot_dir.to_string(), src_dir.to_string()),
+    };
+
+    // Check the new name before anything is touched
+    if renameargs.class {
+        naming::validate_class_name(&renameargs.new_modulename)?;
+        ExtensionApi::load(ws, &path, manifest.as_ref())?.check_class_name(&naming::to_pascal_case(&renameargs.new_modulename))?;
+        rename_class(ws, &path, &renameargs.old_modulename, &renameargs.new_modulename, &src_dir, manifest.as_mut())?;
+    } else {
+        naming::validate_module_name(&renameargs.new_modulename)?;
+        rename_module(ws, &path, &renameargs.old_modulename, &renameargs.new_modulename, &godot_dir, &src_dir, manifest.as_mut())?;
+    };
+
+    if let Some(manifest) = &manifest {
+        manifest.save(ws, &path)?;
+    };
+
+    if !renameargs.nobuild {
+        if let Some(output) = ws.run(&path, "scons")? {
+            _ = print_output(output);
+        };
+    };
+
+    Ok(())
 }
 
 
-pub fn rename(renameargs: RenameArgs) -> Result<(), Error> {
-    let path = renameargs.path.with_context(|| "rename: Tried getting the path argument").unwrap();
-
-    let pathstr = path.to_str().unwrap();
-    let read = std::fs::read_dir(path.clone())
-        .with_context(|| format!("Tried reading {}", pathstr))?
-        .into_iter().flatten()
-        .collect::<Vec<std::fs::DirEntry>>();
-
-    for file in read {
-        match file.file_name().to_str().unwrap() {
-            "godot" => {
-                continue;
-                let extension_list_path = file.path().join(".godot/extension_list.cfg");
-                rename_file_contents(
-                    extension_list_path,
-                    vec![
-                        (format!("{}.gdextension", renameargs.old_modulename), format!("{}.gdextension", renameargs.new_modulename)),
-                    ]
-                );
-                
-                let module_path = file.path().join(format!("{}.gdextension", renameargs.old_modulename));
-                rename_file_contents(
-                    module_path.clone(),
-                    vec![
-                        (format!("{}_library_init", renameargs.old_modulename), format!("{}_library_init", renameargs.new_modulename)),
-                        (format!("libgd{}.", renameargs.old_modulename), format!("libgd{}.", renameargs.new_modulename))
-                    ]
-                );
-                let new_module_path = file.path().join(format!("{}.gdextension", renameargs.new_modulename));
-                std::fs::rename(
-                    module_path.clone(),
-                    new_module_path.clone(),
-                ).with_context(|| format!("Tried renaming {} to {}", module_path.display(), new_module_path.display())).unwrap_or_else(|_| ());
-            },
-            "CMakeLists.txt" => {
-                let cmake_path = file.path();
-                rename_file_contents(
-                    cmake_path.clone(),
-                    vec![
-                        (format!("project({})", renameargs.old_modulename), format!("project({})", renameargs.new_modulename)),
-                        (format!("PROPERTY OUTPUT_NAME \"{}\"", renameargs.old_modulename), format!("PROPERTY OUTPUT_NAME \"{}\"", renameargs.new_modulename))
-                    ]
-                );
-            },
-            "SConstruct" => {
-                let sconstruct_path = file.path();
-                rename_file_contents(
-                    sconstruct_path,
-                    vec![
-                        (format!("libgd{}", renameargs.old_modulename), format!("libgd{}", renameargs.new_modulename))
-                    ]
-                );
-            },
-            "src" => {
-                let old_classname = codegen::get_classname_uppercase(&renameargs.old_modulename);
-                let new_classname = codegen::get_classname_uppercase(&renameargs.new_modulename);
-                let old_uppercase = renameargs.old_modulename.to_uppercase();
-                let new_uppercase = renameargs.new_modulename.to_uppercase();
-
-                
-                // Rename class in the respective CPP file
-                let old_class_cpp_path = file.path().join(format!("{}.cpp", renameargs.old_modulename));
-                let class_cpp_path = file.path().join(format!("{}.cpp", renameargs.new_modulename));
-                rename_file_contents(
-                    old_class_cpp_path.clone(),
-                    vec![
-                        (format!("#include \"{}.h\"", renameargs.old_modulename), format!("#include \"{}.h\"", renameargs.new_modulename)),
-                        (format!("{}::{}()", old_classname, old_classname), format!("{}::{}()", new_classname, new_classname)),
-                        (format!("{}::~{}()", old_classname, old_classname), format!("{}::~{}()", new_classname, new_classname)),
-                        (format!("{}::", old_classname), format!("{}::", new_classname)),
-                    ]
-                );
-                std::fs::rename(
-                    old_class_cpp_path.clone(),
-                    class_cpp_path.clone()
-                ).with_context(|| format!("Tried renaming {} to {}", old_class_cpp_path.display(), class_cpp_path.display())).unwrap_or_else(|_| ());
-                
-                
-                // Rename class in the respective header file
-                let old_header_path = file.path().join(format!("{}.h", renameargs.old_modulename));
-                let header_path = file.path().join(format!("{}.h", renameargs.new_modulename));
-                rename_file_contents(
-                    old_header_path.clone(),
-                    vec![
-                        (format!("{}_CLASS_H", old_uppercase), format!("{}_CLASS_H", new_uppercase)),
-                        (format!("class {}", old_classname), format!("class {}", new_classname)),
-                        (format!("GDCLASS({}", old_classname), format!("GDCLASS({}", new_classname)),
-                        (format!("{}();", old_classname), format!("{}();", new_classname)),
-                    ]
-                );
-                std::fs::rename(
-                    old_header_path.clone(),
-                    header_path.clone()
-                ).with_context(|| format!("Tried renaming {} to {}", old_header_path.display(), header_path.display())).unwrap_or_else(|_| ());
-
-
-                // Rename class in the register_types.h
-                let register_h_path = file.path().join("register_types.h");
-                rename_file_contents(
-                    register_h_path.clone(),
-                    vec![
-                        (format!("{}_REGISTER_TYPES_H", old_uppercase), format!("{}_REGISTER_TYPES_H", new_uppercase)),
-                        (format!("initialize_{}_module", renameargs.old_modulename), format!("initialize_{}_module", renameargs.new_modulename)),
-                    ]
-                );
-
-                
-                // Rename class in the register_types.cpp
-                let register_cpp_path = file.path().join("register_types.cpp");
-                rename_file_contents(
-                    register_cpp_path.clone(),
-                    vec![
-                        (format!("#include \"{}.h\"", renameargs.old_modulename), format!("#include \"{}.h\"", renameargs.new_modulename)),
-                        (format!("initialize_{}_module", renameargs.old_modulename), format!("initialize_{}_module", renameargs.new_modulename)),
-                        (format!("ClassDB::register_class<{}>();", old_classname), format!("ClassDB::register_class<{}>();", new_classname)),
-                        (format!("{}_library_init", renameargs.old_modulename), format!("{}_library_init", renameargs.new_modulename)),
-                    ]
-                );
-            },
-            _ => (),
+/// Renames the module-level identifiers: .gdextension file, library, entry symbol, build targets and register_types.
+/// The classes of the module keep their names.
+fn rename_module(ws: &mut Workspace, path: &Path, old_name: &str, new_name: &str, godot_dir: &str, src_dir: &str, manifest: Option<&mut Manifest>) -> Result<(), Error> {
+    if manifest.as_ref().is_some_and(|m| m.find_module(new_name).is_some()) {
+        return Err(Error::msg(format!("A module named '{}' already exists in the manifest.", new_name)));
+    };
+    let src_folder = path.join(src_dir);
+    let (module_folder, gdextension_file) = match manifest.as_ref().and_then(|m| m.find_module(old_name)) {
+        Some(module) => (path.join(&module.src_dir), module.gdextension.clone()),
+        // Modules added via 'gdsetup add' live in their own subfolder
+        None if ws.is_dir(&src_folder.join(old_name)) => (src_folder.join(old_name), format!("{}.gdextension", old_name)),
+        None => (src_folder.clone(), format!("{}.gdextension", old_name)),
+    };
+    // Only the folder of an added module is named after it, the one of 'gdsetup init' is 'src/' itself
+    let new_module_folder = if module_folder == src_folder.join(old_name) {
+        src_folder.join(new_name)
+    } else {
+        module_folder.clone()
+    };
+    let old_module_dir = format!("{}/{}/", src_dir, old_name);
+    let new_module_dir = format!("{}/{}/", src_dir, new_name);
+
+    let godot_folder = path.join(godot_dir);
+    let new_gdextension = match gdextension_file.rsplit_once('/') {
+        Some((folder, _)) => format!("{}/{}.gdextension", folder, new_name),
+        None => format!("{}.gdextension", new_name),
+    };
+
+    // Godot only loads the extensions listed here. The '.godot' folder is usually not checked in, Godot recreates it
+    let extension_list_path = godot_folder.join(".godot").join("extension_list.cfg");
+    if ws.exists(&extension_list_path) {
+        rename_file_contents(
+            ws,
+            extension_list_path,
+            vec![
+                (format!("res://{}", gdextension_file), format!("res://{}", new_gdextension)),
+            ],
+            &[],
+        )?;
+    };
+
+    let module_path = godot_folder.join(&gdextension_file);
+    rename_file_contents(
+        ws,
+        module_path.clone(),
+        vec![
+            (format!("\"{}_library_init\"", old_name), format!("\"{}_library_init\"", new_name)),
+            (format!("libgd{}.", old_name), format!("libgd{}.", new_name))
+        ],
+        &[],
+    )?;
+    let new_module_path = godot_folder.join(&new_gdextension);
+    if ws.exists(&new_module_path) {
+        warn!("{} already exists, not renaming {}", new_module_path.display(), module_path.display());
+    } else {
+        ws.rename(
+            &module_path,
+            &new_module_path,
+        ).with_context(|| format!("Tried renaming {} to {}", module_path.display(), new_module_path.display()))?;
+        info!("Renamed {} to {}", module_path.display(), new_module_path.display());
+
+        // Newer Godot versions keep a .uid file next to the .gdextension
+        let uid_path = godot_folder.join(format!("{}.uid", gdextension_file));
+        if ws.exists(&uid_path) {
+            let new_uid_path = godot_folder.join(format!("{}.uid", new_gdextension));
+            ws.rename(
+                &uid_path,
+                &new_uid_path,
+            ).with_context(|| format!("Tried renaming {} to {}", uid_path.display(), new_uid_path.display()))?;
         };
     };
 
-    if !renameargs.nobuild {
-        let basecmd = get_basecommand();
-        let basecmd = (basecmd.0.as_str(), basecmd.1.as_str());
-        info!("Running 'scons'");
-        let output = Command::new(basecmd.0).arg(basecmd.1)
-            .current_dir(pathstr)
-            .arg("scons").output();
-        _ = print_output(output.unwrap());
+    // The module created by 'gdsetup init' is the project itself, added modules have their own targets
+    let added_by = |name: &str| format!("# Module '{}' (added via 'gdsetup add {}')", name, name);
+    let is_added = new_module_folder != module_folder;
+    let (old_region, new_region) = (format!("module {}", old_name), format!("module {}", new_name));
+    let (cmake_regions, sconstruct_regions) = if is_added {
+        (vec![old_region.as_str()], vec![old_region.as_str()])
+    } else {
+        (vec!["project", "output"], vec!["library"])
+    };
+    let cmake_path = path.join("CMakeLists.txt");
+    if ws.exists(&cmake_path) {
+        rename_file_contents(
+            ws,
+            cmake_path.clone(),
+            vec![
+                (format!("project({})", old_name), format!("project({})", new_name)),
+                (format!("PROPERTY OUTPUT_NAME \"{}\"", old_name), format!("PROPERTY OUTPUT_NAME \"{}\"", new_name)),
+                (added_by(old_name), added_by(new_name)),
+                (format!("{}_SOURCES {}", old_name, old_module_dir), format!("{}_SOURCES {}", new_name, new_module_dir)),
+                (format!("${{{}_SOURCES}}", old_name), format!("${{{}_SOURCES}}", new_name)),
+                (format!("add_library({} ", old_name), format!("add_library({} ", new_name)),
+                (format!("target_include_directories({} ", old_name), format!("target_include_directories({} ", new_name)),
+                (format!("target_link_directories({}", old_name), format!("target_link_directories({}", new_name)),
+                (format!("target_link_libraries({}", old_name), format!("target_link_libraries({}", new_name)),
+                (format!("set_property(TARGET {} ", old_name), format!("set_property(TARGET {} ", new_name)),
+            ],
+            &cmake_regions,
+        )?;
+        rename_region(ws, &cmake_path, &old_region, &new_region)?;
+    };
+    let sconstruct_path = path.join("SConstruct");
+    if ws.exists(&sconstruct_path) {
+        rename_file_contents(
+            ws,
+            sconstruct_path.clone(),
+            vec![
+                (format!("libgd{}", old_name), format!("libgd{}", new_name)),
+                (added_by(old_name), added_by(new_name)),
+                (format!("sources_{} ", old_name), format!("sources_{} ", new_name)),
+                (format!("sources_{},", old_name), format!("sources_{},", new_name)),
+                (format!("library_{}", old_name), format!("library_{}", new_name)),
+                (format!("Glob(\"{}", old_module_dir), format!("Glob(\"{}", new_module_dir)),
+                (format!("os.walk(\"{}/{}\")", src_dir, old_name), format!("os.walk(\"{}/{}\")", src_dir, new_name)),
+            ],
+            &sconstruct_regions,
+        )?;
+        rename_region(ws, &sconstruct_path, &old_region, &new_region)?;
+    };
+
+    let old_uppercase = naming::to_screaming_snake_case(old_name);
+    let new_uppercase = naming::to_screaming_snake_case(new_name);
+    rename_file_contents(
+        ws,
+        module_folder.join("register_types.h"),
+        vec![
+            (format!("{}_REGISTER_TYPES_H", old_uppercase), format!("{}_REGISTER_TYPES_H", new_uppercase)),
+            (format!("initialize_{}_module", old_name), format!("initialize_{}_module", new_name)),
+            (format!("uninitialize_{}_module", old_name), format!("uninitialize_{}_module", new_name)),
+        ],
+        &["module"],
+    )?;
+    rename_file_contents(
+        ws,
+        module_folder.join("register_types.cpp"),
+        vec![
+            (format!("initialize_{}_module", old_name), format!("initialize_{}_module", new_name)),
+            (format!("uninitialize_{}_module", old_name), format!("uninitialize_{}_module", new_name)),
+            (format!("{}_library_init", old_name), format!("{}_library_init", new_name)),
+        ],
+        &["module"],
+    )?;
+    if is_added {
+        if ws.exists(&new_module_folder) {
+            warn!("{} already exists, not renaming {}", new_module_folder.display(), module_folder.display());
+        } else {
+            ws.rename(&module_folder, &new_module_folder)
+                .with_context(|| format!("Tried renaming {} to {}", module_folder.display(), new_module_folder.display()))?;
+            info!("Renamed {} to {}", module_folder.display(), new_module_folder.display());
+        };
+    };
+
+    if let Some(module) = manifest.and_then(|m| m.find_module_mut(old_name)) {
+        module.name = new_name.to_string();
+        module.entry_symbol = format!("{}_library_init", new_name);
+        module.gdextension = new_gdextension;
+        if is_added {
+            module.src_dir = format!("{}/{}", src_dir, new_name);
+        };
+    };
+
+    Ok(())
+}
+
+
+/// Renames the class-level identifiers: the .h/.cpp files, the C++ class and its registration.
+/// The module the class belongs to keeps its name.
+fn rename_class(ws: &mut Workspace, path: &Path, old_name: &str, new_name: &str, src_dir: &str, manifest: Option<&mut Manifest>) -> Result<(), Error> {
+    let new_classname = naming::to_pascal_case(new_name);
+    let old_uppercase = naming::to_screaming_snake_case(old_name);
+    let new_uppercase = naming::to_screaming_snake_case(new_name);
+
+    let module_folder = match manifest.as_ref() {
+        Some(manifest) => {
+            if manifest.modules.iter().flat_map(|m| m.classes.iter()).any(|c| c.name == new_classname) {
+                return Err(Error::msg(format!("A class named '{}' already exists in the manifest.", new_classname)));
+            };
+            let module = manifest.modules.iter().find(|m| m.classes.iter().any(|c| c.file == old_name))
+                .with_context(|| format!("Could not find a class '{}' in the manifest.", old_name))?;
+            path.join(&module.src_dir)
+        },
+        None => find_class_folder(ws, &path.join(src_dir), old_name)?,
+    };
+    // Classes of older projects might not be PascalCase, so the manifest (or the 'GDCLASS' of the header) knows better
+    let old_classname = manifest.as_ref()
+        .and_then(|m| m.modules.iter().flat_map(|m| m.classes.iter()).find(|c| c.file == old_name))
+        .map(|c| c.name.clone())
+        .or_else(|| ws.read_to_string(&module_folder.join(format!("{}.h", old_name))).ok().and_then(|h| header::get_gdclass_name(&h)))
+        .unwrap_or_else(|| naming::to_pascal_case(old_name));
+
+    // Refuse to overwrite existing classes
+    let old_class_cpp_path = module_folder.join(format!("{}.cpp", old_name));
+    let class_cpp_path = module_folder.join(format!("{}.cpp", new_name));
+    let old_header_path = module_folder.join(format!("{}.h", old_name));
+    let header_path = module_folder.join(format!("{}.h", new_name));
+    for file in [&class_cpp_path, &header_path, &bind::get_generated_path(&header_path)] {
+        if ws.exists(file) {
+            return Err(Error::msg(format!("'{}' already exists.", file.display())));
+        };
+    };
+
+    // Rename class in the respective CPP file
+    rename_file_contents(
+        ws,
+        old_class_cpp_path.clone(),
+        vec![
+            (format!("#include \"{}.h\"", old_name), format!("#include \"{}.h\"", new_name)),
+            (format!("{}::{}()", old_classname, old_classname), format!("{}::{}()", new_classname, new_classname)),
+            (format!("{}::~{}()", old_classname, old_classname), format!("{}::~{}()", new_classname, new_classname)),
+            (format!("{}::", old_classname), format!("{}::", new_classname)),
+        ],
+        &[],
+    )?;
+    ws.rename(
+        &old_class_cpp_path,
+        &class_cpp_path,
+    ).with_context(|| format!("Tried renaming {} to {}", old_class_cpp_path.display(), class_cpp_path.display()))?;
+
+    // Rename class in the respective header file
+    rename_file_contents(
+        ws,
+        old_header_path.clone(),
+        vec![
+            (format!("{}_CLASS_H", old_uppercase), format!("{}_CLASS_H", new_uppercase)),
+            (format!("class {}", old_classname), format!("class {}", new_classname)),
+            (format!("GDCLASS({}", old_classname), format!("GDCLASS({}", new_classname)),
+            (format!("{}();", old_classname), format!("{}();", new_classname)),
+            (format!("{}::", old_classname), format!("{}::", new_classname)),
+            // The enum casts of Godot 4.0 projects, e.g. 'VARIANT_ENUM_CAST(Player, State);'
+            (format!("VARIANT_ENUM_CAST({}, ", old_classname), format!("VARIANT_ENUM_CAST({}, ", new_classname)),
+            (format!("VARIANT_BITFIELD_CAST({}, ", old_classname), format!("VARIANT_BITFIELD_CAST({}, ", new_classname)),
+        ],
+        &[],
+    )?;
+    ws.rename(
+        &old_header_path,
+        &header_path,
+    ).with_context(|| format!("Tried renaming {} to {}", old_header_path.display(), header_path.display()))?;
+
+    // The bindings generated from the annotations of the header ('gdsetup bind')
+    let old_generated_path = bind::get_generated_path(&old_header_path);
+    if ws.exists(&old_generated_path) {
+        let generated_path = bind::get_generated_path(&header_path);
+        rename_file_contents(
+            ws,
+            old_generated_path.clone(),
+            vec![
+                (format!("\"{}.h\"", old_name), format!("\"{}.h\"", new_name)),
+                (format!("'{}.h'", old_name), format!("'{}.h'", new_name)),
+                (format!("{}::", old_classname), format!("{}::", new_classname)),
+                (format!("\"{}\", D_METHOD", old_classname), format!("\"{}\", D_METHOD", new_classname)),
+            ],
+            &[],
+        )?;
+        ws.rename(&old_generated_path, &generated_path)
+            .with_context(|| format!("Tried renaming {} to {}", old_generated_path.display(), generated_path.display()))?;
+    };
+
+    // Rename class in the register_types.cpp of its module
+    rename_file_contents(
+        ws,
+        module_folder.join("register_types.cpp"),
+        vec![
+            (format!("#include \"{}.h\"", old_name), format!("#include \"{}.h\"", new_name)),
+            (format!("ClassDB::register_class<{}>();", old_classname), format!("ClassDB::register_class<{}>();", new_classname)),
+            (format!("GDREGISTER_VIRTUAL_CLASS({});", old_classname), format!("GDREGISTER_VIRTUAL_CLASS({});", new_classname)),
+            (format!("GDREGISTER_ABSTRACT_CLASS({});", old_classname), format!("GDREGISTER_ABSTRACT_CLASS({});", new_classname)),
+            (format!("EditorPlugins::add_by_type<{}>();", old_classname), format!("EditorPlugins::add_by_type<{}>();", new_classname)),
+        ],
+        &["includes", "registrations"],
+    )?;
+
+    if let Some(class) = manifest.and_then(|m| m.modules.iter_mut().flat_map(|m| m.classes.iter_mut()).find(|c| c.file == old_name)) {
+        class.name = new_classname;
+        class.file = new_name.to_string();
     };
 
     Ok(())
-}
\ No newline at end of file
+}
+
+
+/// Renames the region of an added module inside a build file, if it has one
+fn rename_region(ws: &mut Workspace, filepath: &Path, old_region: &str, new_region: &str) -> Result<(), Error> {
+    let contents = ws.read_to_string(filepath).with_context(|| format!("Tried reading '{}'", filepath.display()))?;
+    if regions::find_region(&contents, old_region).is_some() {
+        ws.write(filepath, regions::rename_region(&contents, old_region, new_region))
+            .with_context(|| format!("Tried writing '{}'", filepath.display()))?;
+    };
+    Ok(())
+}
+
+
+/// Finds the module folder which contains the files of a class in projects without a manifest
+fn find_class_folder(ws: &Workspace, src_folder: &Path, classname: &str) -> Result<PathBuf, Error> {
+    let header = format!("{}.h", classname);
+    if ws.exists(&src_folder.join(&header)) {
+        return Ok(src_folder.to_path_buf());
+    };
+    for entry in ws.read_dir(src_folder).unwrap_or_default() {
+        if ws.is_dir(&entry) && ws.exists(&entry.join(&header)) {
+            return Ok(entry);
+        };
+    };
+    Err(Error::msg(format!("Could not find the class '{}' inside '{}'.", classname, src_folder.display())))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init, add_extension, AddModuleArgs, InitArgs};
+
+    /// Creates a project inside a dry-run workspace, nothing is written to the disk
+    fn init_project(ws: &mut Workspace, name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gdsetup-test-{}-{}", name, std::process::id()));
+        let initargs = InitArgs {
+            path: Some(path.clone()),
+            path2: None,
+            nobuild: true,
+            base: "Node".to_string(),
+            godot_version: "4.3".to_string(),
+            godot_dir: None,
+            src_dir: None,
+            module: None,
+            class: None,
+            template: None,
+            vars: vec![],
+        };
+        init(ws, initargs, None, "example", "godot", "src").unwrap();
+        path
+    }
+
+    #[test]
+    fn rename_added_module_updates_cmake_targets() {
+        let mut ws = Workspace::new(true);
+        let path = init_project(&mut ws, "rename-module");
+        let mut manifest = Manifest::load(&ws, &path).unwrap();
+        add_extension(&mut ws, &path, &AddModuleArgs { name: "mod2".to_string(), class: None }, "Node", "godot", "src", manifest.as_mut()).unwrap();
+        rename_module(&mut ws, &path, "mod2", "mod3", "godot", "src", manifest.as_mut()).unwrap();
+
+        let cmakelists = ws.read_to_string(&path.join("CMakeLists.txt")).unwrap();
+        assert!(!cmakelists.contains("mod2"), "{}", cmakelists);
+        for target in ["add_library(mod3 ", "target_include_directories(mod3 ", "target_link_directories(mod3", "target_link_libraries(mod3", "set_property(TARGET mod3 "] {
+            assert!(cmakelists.contains(target), "missing '{}'", target);
+        };
+        assert!(cmakelists.contains("# gdsetup:begin module mod3"));
+
+        let sconstruct = ws.read_to_string(&path.join("SConstruct")).unwrap();
+        assert!(!sconstruct.contains("mod2"), "{}", sconstruct);
+        assert!(sconstruct.contains("os.walk(\"src/mod3\")"));
+    }
+
+    #[test]
+    fn rename_module_keeps_modules_with_a_longer_name() {
+        let mut ws = Workspace::new(true);
+        let path = init_project(&mut ws, "rename-prefix");
+        let mut manifest = Manifest::load(&ws, &path).unwrap();
+        add_extension(&mut ws, &path, &AddModuleArgs { name: "example2".to_string(), class: None }, "Node", "godot", "src", manifest.as_mut()).unwrap();
+        rename_module(&mut ws, &path, "example", "game", "godot", "src", manifest.as_mut()).unwrap();
+
+        let sconstruct = ws.read_to_string(&path.join("SConstruct")).unwrap();
+        assert!(!sconstruct.contains("game2"), "{}", sconstruct);
+        assert!(sconstruct.contains("libgdgame") && sconstruct.contains("libgdexample2"));
+        assert!(sconstruct.contains("library_example2") && sconstruct.contains("# gdsetup:begin module example2"));
+
+        let cmakelists = ws.read_to_string(&path.join("CMakeLists.txt")).unwrap();
+        assert!(!cmakelists.contains("game2"), "{}", cmakelists);
+        assert!(cmakelists.contains("project(game)"));
+        for target in ["add_library(example2 ", "target_link_directories(example2", "target_link_libraries(example2", "set_property(TARGET example2 "] {
+            assert!(cmakelists.contains(target), "missing '{}'", target);
+        };
+    }
+
+    #[test]
+    fn rename_class_keeps_classes_with_a_longer_name() {
+        let mut ws = Workspace::new(true);
+        let path = init_project(&mut ws, "rename-class");
+        let cpp_path = path.join("src").join("example.cpp");
+        let cpp = ws.read_to_string(&cpp_path).unwrap();
+        ws.write(&cpp_path, format!("{}\nint helper() {{ return MyExample::count() + ExampleState::count(); }}\n", cpp)).unwrap();
+        let mut manifest = Manifest::load(&ws, &path).unwrap();
+        rename_class(&mut ws, &path, "example", "hero", "src", manifest.as_mut()).unwrap();
+
+        let cpp = ws.read_to_string(&path.join("src").join("hero.cpp")).unwrap();
+        assert!(cpp.contains("Hero::") && !cpp.contains(" Example::"), "{}", cpp);
+        assert!(cpp.contains("MyExample::count() + ExampleState::count()"), "{}", cpp);
+        let register_cpp = ws.read_to_string(&path.join("src").join("register_types.cpp")).unwrap();
+        assert!(register_cpp.contains("ClassDB::register_class<Hero>();") && register_cpp.contains("#include \"hero.h\""));
+    }
+}
@@ -0,0 +1,390 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Error, Context};
+use log::{info, debug, warn};
+
+use crate::{SyncArgs, api::ExtensionApi, level::{self, InitLevel, Registrations}, manifest::{Manifest, Class}, regions, version::GodotVersion, workspace::Workspace};
+
+
+/// How a class has to be registered
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassKind {
+    /// Can be instantiated by the editor and scripts
+    Normal,
+    /// Has methods scripts can override ('GDVIRTUAL*'), only scripts extending it can be instantiated
+    Virtual,
+    /// Has pure virtual methods and can't be instantiated at all
+    Abstract,
+}
+
+/// A class declared with 'GDCLASS(Name, Base)' inside a header of a module
+struct FoundClass {
+    name: String,
+    base: String,
+    kind: ClassKind,
+    /// Path of the header relative to the module folder, e.g. 'player.h' or 'enemies/slime.h'
+    header: String,
+    level: InitLevel,
+}
+
+
+
+pub fn sync(ws: &mut Workspace, syncargs: SyncArgs, src_dir: &str) -> Result<(), Error> {
+    let path = match syncargs.path {
+        Some(path) => path,
+        None => std::env::current_dir().with_context(|| "Getting current directory.")?,
+    };
+
+    ws.set_root(&path);
+    let mut manifest = Manifest::load(ws, &path)?;
+    let src_dir = match &manifest {
+        Some(manifest) => manifest.project.src_dir.clone(),
+        None => src_dir.to_string(),
+    };
+
+    // Every folder with a register_types.cpp is a module, the classes of its subfolders belong to it
+    let mut module_folders = vec![];
+    find_module_folders(ws, &path.join(&src_dir), &mut module_folders)?;
+    if module_folders.is_empty() {
+        return Err(Error::msg(format!("Could not find a register_types.cpp inside '{}'. Did you run 'gdsetup init' there?", path.join(&src_dir).display())));
+    };
+
+    let api = ExtensionApi::load(ws, &path, manifest.as_ref())?;
+    let version = manifest.as_ref().map(|m| m.project.godot_version).unwrap_or_default();
+    for folder in &module_folders {
+        // Levels set in the manifest win over the ones derived from the base classes
+        let tagged = manifest.as_ref()
+            .and_then(|m| m.modules.iter().find(|m| path.join(&m.src_dir) == *folder))
+            .map(|m| m.classes.clone())
+            .unwrap_or_default();
+        let classes = sync_module(ws, folder, &tagged, &api, version)?;
+        if let Some(manifest) = manifest.as_mut() {
+            update_manifest(manifest, &path, folder, &classes);
+        };
+    };
+
+    if let Some(manifest) = manifest {
+        manifest.save(ws, &path)?;
+    };
+
+    Ok(())
+}
+
+
+/// Collects every folder with a register_types.cpp, each one is a module
+pub fn find_module_folders(ws: &Workspace, folder: &Path, folders: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if ws.exists(&folder.join("register_types.cpp")) {
+        folders.push(folder.to_path_buf());
+    };
+    let mut entries = ws.read_dir(folder).with_context(|| format!("Tried reading '{}'", folder.display()))?;
+    entries.sort();
+    for entry in entries {
+        if ws.is_dir(&entry) && !is_hidden(&entry) {
+            find_module_folders(ws, &entry, folders)?;
+        };
+    };
+    Ok(())
+}
+
+
+/// Collects the headers of a module, subfolders with their own register_types.cpp are other modules
+pub fn find_headers(ws: &Workspace, folder: &Path, headers: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let mut entries = ws.read_dir(folder).with_context(|| format!("Tried reading '{}'", folder.display()))?;
+    entries.sort();
+    for entry in entries {
+        if ws.is_dir(&entry) {
+            if !is_hidden(&entry) && !ws.exists(&entry.join("register_types.cpp")) {
+                find_headers(ws, &entry, headers)?;
+            };
+        } else if entry.extension().is_some_and(|ext| ext == "h" || ext == "hpp") && entry.file_name().is_some_and(|name| name != "register_types.h") {
+            headers.push(entry);
+        };
+    };
+    Ok(())
+}
+
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with('.'))
+}
+
+
+/// Rewrites the includes and registrations of a module's register_types.cpp and returns the classes it found
+fn sync_module(ws: &mut Workspace, folder: &Path, tagged: &[Class], api: &ExtensionApi, version: GodotVersion) -> Result<Vec<FoundClass>, Error> {
+    let mut headers = vec![];
+    find_headers(ws, folder, &mut headers)?;
+    let mut classes = vec![];
+    let mut includes = vec![];
+    for header in &headers {
+        let contents = ws.read_to_string(header).with_context(|| format!("Tried reading '{}'", header.display()))?;
+        let relative = header.strip_prefix(folder).unwrap_or(header).to_string_lossy().replace('\\', "/");
+        let found = find_classes(&contents, &relative);
+        if !found.is_empty() {
+            includes.push(format!("#include \"{}\"", relative));
+        };
+        classes.extend(found);
+    };
+    let mut classes = sort_by_base(classes);
+    assign_levels(&mut classes, tagged, api);
+    let mut registrations = Registrations::new();
+    for class in &classes {
+        let registered = registrations.entry(class.level).or_default();
+        registered.push(match class.kind {
+            ClassKind::Normal => format!("ClassDB::register_class<{}>();", class.name),
+            ClassKind::Virtual => format!("GDREGISTER_VIRTUAL_CLASS({});", class.name),
+            ClassKind::Abstract => format!("GDREGISTER_ABSTRACT_CLASS({});", class.name),
+        });
+        // Only plugins the editor can instantiate can be added
+        if class.kind == ClassKind::Normal {
+            registered.extend(level::get_plugin_registration(&class.name, get_engine_base(&classes, class), version));
+        };
+    };
+
+    let register_cpp_path = folder.join("register_types.cpp");
+    let register_cpp = ws.read_to_string(&register_cpp_path)
+        .with_context(|| format!("Tried reading '{}'", register_cpp_path.display()))?;
+    let synced = add_markers(&register_cpp)
+        .and_then(|contents| level::set_registrations(&contents, &includes, &registrations))
+        .with_context(|| format!("Could not sync '{}'", register_cpp_path.display()))?;
+    if synced == register_cpp {
+        debug!("'{}' is up to date", register_cpp_path.display());
+    } else {
+        info!("Registering {} classes in '{}'", classes.len(), register_cpp_path.display());
+        ws.write(&register_cpp_path, synced).with_context(|| format!("Tried writing '{}'", register_cpp_path.display()))?;
+    };
+    Ok(classes)
+}
+
+
+/// Sets the level of every class, base classes have to come first. A level from the manifest wins, otherwise a class
+/// is registered with its base class or, for engine base classes, at the level the base class exists at
+fn assign_levels(classes: &mut [FoundClass], tagged: &[Class], api: &ExtensionApi) {
+    for idx in 0..classes.len() {
+        let base_level = classes[..idx].iter().find(|other| other.name == classes[idx].base).map(|base| base.level);
+        let derived = base_level.unwrap_or_else(|| InitLevel::for_base(&classes[idx].base, api));
+        let class = &mut classes[idx];
+        class.level = match tagged.iter().find(|c| c.name == class.name).and_then(|c| c.level) {
+            Some(level) if base_level.is_some_and(|base_level| level < base_level) => {
+                warn!("'{}' can't be registered at the {} level before its base class '{}', registering it at the {} level.", class.name, level, class.base, derived);
+                derived
+            },
+            Some(level) => level,
+            None => derived,
+        };
+    };
+}
+
+
+/// Returns the engine class a class inherits from, following the classes of the module
+fn get_engine_base<'a>(classes: &'a [FoundClass], class: &'a FoundClass) -> &'a str {
+    let mut base = class.base.as_str();
+    // A cycle can't compile anyway, don't follow it forever
+    for _ in 0..classes.len() {
+        match classes.iter().find(|other| other.name == base) {
+            Some(other) => base = other.base.as_str(),
+            None => break,
+        };
+    };
+    base
+}
+
+
+/// Returns every class of a header. A class ends where the next 'GDCLASS' starts
+fn find_classes(header: &str, relative: &str) -> Vec<FoundClass> {
+    let lines = strip_block_comments(header);
+    let lines = lines.iter().map(|l| l.as_str()).collect::<Vec<&str>>();
+    let mut classes = vec![];
+    for (idx, line) in lines.iter().enumerate() {
+        let Some((name, base)) = line.strip_prefix("GDCLASS(")
+            .and_then(|args| args.split_once(')'))
+            .and_then(|(args, _)| args.split_once(',')) else {
+            continue;
+        };
+        let body = lines.iter().skip(idx + 1).take_while(|l| !l.starts_with("GDCLASS("));
+        let kind = if body.clone().any(|l| is_pure_virtual(l)) {
+            ClassKind::Abstract
+        } else if body.clone().any(|l| l.starts_with("GDVIRTUAL")) {
+            ClassKind::Virtual
+        } else {
+            ClassKind::Normal
+        };
+        classes.push(FoundClass { name: name.trim().to_string(), base: base.trim().to_string(), kind, header: relative.to_string(), level: InitLevel::Scene });
+    };
+    classes
+}
+
+
+/// Returns the trimmed lines of a header without '/* ... */' comments, a 'GDCLASS' inside of them declares nothing
+fn strip_block_comments(header: &str) -> Vec<String> {
+    let mut in_comment = false;
+    header.lines()
+        .map(|line| {
+            let mut kept = String::new();
+            let mut rest = line;
+            loop {
+                let marker = if in_comment { "*/" } else { "/*" };
+                let Some(idx) = rest.find(marker) else {
+                    if !in_comment {
+                        kept.push_str(rest);
+                    };
+                    break;
+                };
+                if !in_comment {
+                    kept.push_str(&rest[..idx]);
+                };
+                in_comment = !in_comment;
+                rest = &rest[idx + 2..];
+            };
+            kept.trim().to_string()
+        })
+        .collect()
+}
+
+
+/// Whether a line declares a pure virtual method, e.g. 'virtual void attack() = 0;'
+fn is_pure_virtual(line: &str) -> bool {
+    let line = line.split("//").next().unwrap_or_default().trim();
+    match line.strip_suffix("= 0;").or(line.strip_suffix("=0;")) {
+        Some(declaration) => {
+            let declaration = declaration.trim_end();
+            declaration.ends_with(')') || declaration.ends_with("const") || declaration.ends_with("override")
+        },
+        None => false,
+    }
+}
+
+
+/// Orders the classes so that a base class is registered before the classes which inherit from it
+fn sort_by_base(mut classes: Vec<FoundClass>) -> Vec<FoundClass> {
+    let mut sorted: Vec<FoundClass> = vec![];
+    while !classes.is_empty() {
+        let ready = classes.iter()
+            .position(|class| !classes.iter().any(|other| other.name == class.base))
+            .unwrap_or(0);
+        sorted.push(classes.remove(ready));
+    };
+    sorted
+}
+
+
+/// Projects generated before the markers existed get them around their includes and registrations
+pub fn add_markers(register_cpp: &str) -> Result<String, Error> {
+    let mut contents = register_cpp.to_string();
+    if regions::find_region(&contents, "includes").is_none() {
+        let lines = contents.lines().collect::<Vec<&str>>();
+        let is_include = |l: &&str| l.starts_with("#include \"") && !l.contains("register_types.h");
+        let (Some(first), Some(last)) = (lines.iter().position(is_include), lines.iter().rposition(is_include)) else {
+            return Err(Error::msg(format!("Could not find the class includes, please put them between '// {} includes' and '// {}'.", regions::BEGIN_MARKER, regions::END_MARKER)));
+        };
+        contents = regions::wrap_region(&contents, "includes", "//", first, last);
+    };
+    if regions::find_region(&contents, "registrations").is_none() {
+        let lines = contents.lines().collect::<Vec<&str>>();
+        let is_registration = |l: &&str| l.trim().starts_with("ClassDB::register_") || l.trim().starts_with("GDREGISTER_");
+        let (Some(first), Some(last)) = (lines.iter().position(is_registration), lines.iter().rposition(is_registration)) else {
+            return Err(Error::msg(format!("Could not find the class registrations, please put them between '// {} registrations' and '// {}'.", regions::BEGIN_MARKER, regions::END_MARKER)));
+        };
+        contents = regions::wrap_region(&contents, "registrations", "//", first, last);
+    };
+    Ok(contents)
+}
+
+
+/// Records the classes of a module in the manifest, classes which no longer exist are removed
+fn update_manifest(manifest: &mut Manifest, path: &Path, folder: &Path, classes: &[FoundClass]) {
+    let Some(module) = manifest.modules.iter_mut().find(|m| path.join(&m.src_dir) == folder) else {
+        return;
+    };
+    module.classes.retain(|class| {
+        let exists = classes.iter().any(|found| found.name == class.name);
+        if !exists {
+            info!("Removing the class '{}' from the manifest, it does not exist anymore", class.name);
+        };
+        exists
+    });
+    for found in classes {
+        match module.classes.iter_mut().find(|class| class.name == found.name) {
+            Some(class) => class.base = found.base.clone(),
+            None => module.classes.push(Class {
+                name: found.name.clone(),
+                file: found.header.trim_end_matches(".hpp").trim_end_matches(".h").to_string(),
+                base: found.base.clone(),
+                level: None,
+            }),
+        };
+    };
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(name: &str, base: &str) -> FoundClass {
+        FoundClass { name: name.to_string(), base: base.to_string(), kind: ClassKind::Normal, header: format!("{}.h", name), level: InitLevel::Scene }
+    }
+
+    #[test]
+    fn finds_classes_and_their_kind() {
+        let header = "\
+class Enemy : public Node {
+    GDCLASS(Enemy, Node)
+    virtual void attack() = 0;
+};
+
+class Slime : public Enemy {
+    GDCLASS(Slime, Enemy)
+    GDVIRTUAL0(_split)
+};
+
+class Boss : public Slime {
+    GDCLASS( Boss , Slime );
+    void attack() override;
+};
+";
+        let classes = find_classes(header, "enemies/enemy.h");
+        let summary = classes.iter().map(|c| (c.name.as_str(), c.base.as_str(), c.kind)).collect::<Vec<_>>();
+        assert_eq!(summary, vec![("Enemy", "Node", ClassKind::Abstract), ("Slime", "Enemy", ClassKind::Virtual), ("Boss", "Slime", ClassKind::Normal)]);
+        assert!(classes.iter().all(|c| c.header == "enemies/enemy.h"));
+    }
+
+    #[test]
+    fn ignores_classes_in_comments() {
+        let header = "\
+// GDCLASS(Commented, Node)
+/* GDCLASS(Inline, Node) */
+/*
+    GDCLASS(Block, Node)
+    virtual void attack() = 0;
+*/
+class Real : public Node {
+    GDCLASS(Real, Node)
+    // virtual void jump() = 0;
+};
+";
+        let classes = find_classes(header, "real.h");
+        assert_eq!(classes.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["Real"]);
+        assert_eq!(classes[0].kind, ClassKind::Normal);
+    }
+
+    #[test]
+    fn detects_pure_virtual_methods() {
+        assert!(is_pure_virtual("virtual void attack() = 0;"));
+        assert!(is_pure_virtual("virtual int get_damage() const =0;"));
+        assert!(is_pure_virtual("virtual void hit(int p_damage) override = 0; // must be implemented"));
+        assert!(!is_pure_virtual("int count = 0;"));
+        assert!(!is_pure_virtual("// virtual void attack() = 0;"));
+        assert!(!is_pure_virtual("virtual void attack();"));
+    }
+
+    #[test]
+    fn sorts_base_classes_first() {
+        let classes = vec![found("Boss", "Slime"), found("Slime", "Enemy"), found("Player", "Node"), found("Enemy", "Node")];
+        let sorted = sort_by_base(classes);
+        assert_eq!(sorted.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["Player", "Enemy", "Slime", "Boss"]);
+
+        // A cycle can't be sorted, but every class is kept
+        let sorted = sort_by_base(vec![found("A", "B"), found("B", "A")]);
+        assert_eq!(sorted.len(), 2);
+    }
+}
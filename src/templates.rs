@@ -0,0 +1,97 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use anyhow::{Result, Error, Context};
+use log::debug;
+use minijinja::{Environment, UndefinedBehavior, Value};
+
+use crate::workspace::Workspace;
+
+
+/// Folder (relative to the project root) whose templates replace the built-in ones
+pub const TEMPLATES_DIR: &str = ".gdsetup/templates";
+
+/// The templates gdsetup generates files from. An override has to use the same file name.
+pub const BUILTIN_TEMPLATES: [(&str, &str); 11] = [
+    ("register_types.h", include_str!("../templates/register_types.h")),
+    ("register_types.cpp", include_str!("../templates/register_types.cpp")),
+    ("class.h", include_str!("../templates/class.h")),
+    ("class.cpp", include_str!("../templates/class.cpp")),
+    ("class.gen.cpp", include_str!("../templates/class.gen.cpp")),
+    ("SConstruct", include_str!("../templates/SConstruct")),
+    ("SConstruct.module", include_str!("../templates/SConstruct.module")),
+    ("CMakeLists.txt", include_str!("../templates/CMakeLists.txt")),
+    ("CMakeLists.module.txt", include_str!("../templates/CMakeLists.module.txt")),
+    ("module.gdextension", include_str!("../templates/module.gdextension")),
+    ("extension_list.cfg", include_str!("../templates/extension_list.cfg")),
+];
+
+
+/// The templates used by codegen. Project-local overrides win over the ones inside the user config directory.
+#[derive(Debug, Clone, Default)]
+pub struct Templates {
+    overrides: BTreeMap<String, String>,
+}
+
+
+/// Folder inside the user config directory (e.g. '~/.config/gdsetup/templates') whose templates replace the built-in ones
+pub fn get_user_templates_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("gdsetup").join("templates"))
+}
+
+
+impl Templates {
+    /// Only the built-in templates
+    pub fn builtin() -> Self {
+        Templates::default()
+    }
+
+    /// Looks for overrides inside the user config directory and '.gdsetup/templates/' of the project at 'root'
+    pub fn load(ws: &Workspace, root: &Path) -> Result<Self, Error> {
+        let mut templates = Templates::default();
+        if let Some(user_dir) = get_user_templates_dir() {
+            templates.add_overrides(ws, &user_dir)?;
+        };
+        templates.add_overrides(ws, &root.join(TEMPLATES_DIR))?;
+        Ok(templates)
+    }
+
+    fn add_overrides(&mut self, ws: &Workspace, dir: &Path) -> Result<(), Error> {
+        for (name, _) in BUILTIN_TEMPLATES {
+            let path = dir.join(name);
+            if !ws.exists(&path) {
+                continue;
+            };
+            debug!("Using template '{}'", path.display());
+            let source = ws.read_to_string(&path).with_context(|| format!("Tried reading template '{}'", path.display()))?;
+            self.overrides.insert(name.to_string(), source);
+        };
+        Ok(())
+    }
+
+    /// Returns the source of a template, either the override or the built-in one
+    pub fn source(&self, name: &str) -> Option<&str> {
+        match self.overrides.get(name) {
+            Some(source) => Some(source.as_str()),
+            None => BUILTIN_TEMPLATES.iter().find(|(builtin, _)| *builtin == name).map(|(_, source)| *source),
+        }
+    }
+
+    /// Renders the template 'name' with the given variables (see 'minijinja::context!')
+    pub fn render(&self, name: &str, context: Value) -> Result<String, Error> {
+        let source = self.source(name).with_context(|| format!("There is no template named '{}'", name))?;
+        render_str(name, source, context)
+    }
+}
+
+
+/// Renders a template source. Every generated file goes through this, so all templates behave the same.
+pub fn render_str(name: &str, source: &str, context: Value) -> Result<String, Error> {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_keep_trailing_newline(true);
+    // A typo inside a template should not silently produce broken code
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    let template = env.template_from_named_str(name, source).with_context(|| format!("Tried parsing template '{}'", name))?;
+    template.render(context).with_context(|| format!("Tried rendering template '{}'", name))
+}
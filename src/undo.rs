@@ -0,0 +1,99 @@
+use anyhow::{Result, Error, Context};
+use log::info;
+
+use crate::{UndoArgs, journal, workspace::{Operation, Workspace}};
+
+
+
+pub fn undo(ws: &mut Workspace, undoargs: UndoArgs) -> Result<(), Error> {
+    let path = match undoargs.path {
+        Some(path) => path,
+        None => std::env::current_dir().with_context(|| "Getting current directory.")?,
+    };
+
+    let operations = journal::load(&path, undoargs.force)?;
+    info!("Undoing {} operation(s) inside '{}'", operations.len(), path.display());
+    ws.revert(&operations)?;
+    if ws.is_dry_run() {
+        return Ok(());
+    };
+
+    // The last command can only be undone once
+    journal::clear(&path)?;
+    // Folders which still contained the journal could not be removed before
+    for operation in operations.iter().rev() {
+        if let Operation::CreateDir(dir) = operation {
+            if dir.is_dir() {
+                _ = std::fs::remove_dir(dir);
+            };
+        };
+    };
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    /// Runs a small command inside a fresh project folder and saves its journal
+    fn run_command(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gdsetup-test-{}-{}", name, std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".gitignore"), "bin/\n").unwrap();
+        std::fs::write(dir.join("project.godot"), "godot").unwrap();
+
+        let mut ws = Workspace::new(false);
+        ws.set_root(&dir);
+        ws.create_dir(&dir.join("godot")).unwrap();
+        ws.rename(&dir.join("project.godot"), &dir.join("godot/project.godot")).unwrap();
+        ws.create_dir(&dir.join("src")).unwrap();
+        ws.write(&dir.join("src/player.cpp"), "player").unwrap();
+        ws.write(&dir.join(".gitignore"), "bin/\n.gdsetup/undo/\n").unwrap();
+        journal::save(&ws).unwrap();
+        dir
+    }
+
+    fn undo_args(path: &Path, force: bool) -> UndoArgs {
+        UndoArgs { path: Some(path.to_path_buf()), force }
+    }
+
+    fn list(dir: &Path) -> Vec<String> {
+        let mut entries = std::fs::read_dir(dir).unwrap().flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect::<Vec<String>>();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn undo_leaves_the_folder_as_it_was() {
+        let dir = run_command("undo");
+        assert_eq!(list(&dir), vec![".gdsetup", ".gitignore", "godot", "src"]);
+
+        undo(&mut Workspace::new(false), undo_args(&dir, false)).unwrap();
+        assert_eq!(list(&dir), vec![".gitignore", "project.godot"]);
+        assert_eq!(std::fs::read_to_string(dir.join(".gitignore")).unwrap(), "bin/\n");
+        // It can only be undone once
+        assert!(undo(&mut Workspace::new(false), undo_args(&dir, false)).is_err());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn undo_refuses_to_overwrite_edits() {
+        let dir = run_command("undo-edited");
+        std::fs::write(dir.join("src/player.cpp"), "edited player").unwrap();
+        std::fs::write(dir.join("project.godot"), "new project").unwrap();
+
+        let err = undo(&mut Workspace::new(false), undo_args(&dir, false)).unwrap_err().to_string();
+        assert!(err.contains("player.cpp") && err.contains("project.godot") && !err.contains(".gitignore"), "{}", err);
+        assert_eq!(std::fs::read_to_string(dir.join("src/player.cpp")).unwrap(), "edited player");
+        assert!(dir.join(journal::JOURNAL_DIR).is_dir());
+
+        std::fs::remove_file(dir.join("project.godot")).unwrap();
+        undo(&mut Workspace::new(false), undo_args(&dir, true)).unwrap();
+        assert!(!dir.join("src").exists());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
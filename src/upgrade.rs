@@ -0,0 +1,370 @@
+use std::{io::{IsTerminal, Write}, path::{Path, PathBuf}};
+
+use anyhow::{Result, Error, Context};
+use log::{info, debug, warn};
+
+use crate::{UpgradeArgs, codegen, print_output, manifest::Manifest, regions, version::GodotVersion, workspace::Workspace};
+
+
+/// The generated files of a single GDExtension module
+struct ModuleFiles {
+    name: String,
+    entry_symbol: String,
+    register_cpp: PathBuf,
+    gdextension: PathBuf,
+}
+
+
+
+pub fn upgrade(ws: &mut Workspace, upgradeargs: UpgradeArgs, godot_dir: &str, src_dir: &str) -> Result<(), Error> {
+    let path = match upgradeargs.path {
+        Some(path) => path,
+        None => std::env::current_dir().with_context(|| "Getting current directory.")?,
+    };
+    let target = upgradeargs.to.parse::<GodotVersion>()?;
+
+    ws.set_root(&path);
+    // Prefer the folders recorded in the manifest over the defaults
+    let manifest = Manifest::load(ws, &path)?;
+    let (godot_dir, src_dir) = match &manifest {
+        Some(manifest) => (manifest.project.godot_dir.clone(), manifest.project.src_dir.clone()),
+        None => (godot_dir.to_string(), src_dir.to_string()),
+    };
+
+    let modules = get_modules(ws, &path, &godot_dir, &src_dir, manifest.as_ref())?;
+    if modules.is_empty() {
+        return Err(Error::msg(format!("Could not find any GDExtension module inside '{}'. Did you run 'gdsetup init' there?", path.display())));
+    };
+    let current = match &manifest {
+        Some(manifest) => manifest.project.godot_version,
+        None => detect_version(ws, &modules),
+    };
+    if target < current {
+        return Err(Error::msg(format!("The project already targets Godot {}, downgrading to {} is not supported.", current, target)));
+    };
+    if target == current {
+        info!("The project already targets Godot {}, there is nothing to upgrade.", current);
+        return Ok(());
+    };
+    info!("Upgrading the project from Godot {} to {}", current, target);
+
+    // Show everything that is going to change before touching the project
+    if !ws.is_dry_run() && !upgradeargs.yes {
+        let mut preview = Workspace::new(true);
+        preview.set_root(&path);
+        upgrade_project(&mut preview, &path, target, &src_dir, &modules, manifest.clone())?;
+        preview.print_plan();
+        if !confirm("Apply these changes?")? {
+            info!("Upgrade cancelled, nothing has been changed.");
+            return Ok(());
+        };
+    };
+    upgrade_project(ws, &path, target, &src_dir, &modules, manifest)?;
+
+    if !upgradeargs.nobuild {
+        match ws.run(&path, "scons") {
+            Ok(Some(output)) => _ = print_output(output),
+            Ok(None) => (),
+            Err(err) => debug!("Could not run 'scons': {}", err),
+        };
+    };
+
+    Ok(())
+}
+
+
+fn upgrade_project(ws: &mut Workspace, path: &Path, target: GodotVersion, src_dir: &str, modules: &[ModuleFiles], manifest: Option<Manifest>) -> Result<(), Error> {
+    for module in modules {
+        match ws.read_to_string(&module.register_cpp) {
+            Ok(register_cpp) => match upgrade_register_cpp(&register_cpp, &module.entry_symbol, target) {
+                Some(register_cpp) => ws.write(&module.register_cpp, register_cpp)
+                    .with_context(|| format!("Tried writing '{}'", module.register_cpp.display()))?,
+                None => warn!("Could not find the entry point '{}' in '{}', please update it yourself.", module.entry_symbol, module.register_cpp.display()),
+            },
+            Err(err) => warn!("Could not read the register_types.cpp of module '{}': {}", module.name, err),
+        };
+
+        let gdextension = ws.read_to_string(&module.gdextension)
+            .with_context(|| format!("Tried reading '{}'", module.gdextension.display()))?;
+        ws.write(&module.gdextension, upgrade_gdextension(&gdextension, target))
+            .with_context(|| format!("Tried writing '{}'", module.gdextension.display()))?;
+    };
+
+    let sconstruct_path = path.join("SConstruct");
+    if ws.exists(&sconstruct_path) {
+        let sconstruct = ws.read_to_string(&sconstruct_path).with_context(|| "Tried reading SConstruct")?;
+        ws.write(&sconstruct_path, upgrade_sconstruct(&sconstruct, src_dir, target)).with_context(|| "Tried writing SConstruct")?;
+    };
+
+    // Switch godot-cpp to the branch of the new version
+    if ws.exists(&path.join(".gitmodules")) && ws.is_dir(&path.join("godot-cpp")) {
+        for command in [
+            format!("git submodule set-branch --branch {} godot-cpp", target.godot_cpp_branch()),
+            "git submodule update --init --remote godot-cpp".to_string(),
+        ] {
+            if let Some(output) = ws.run(path, &command).with_context(|| "Tried to find git")? {
+                if !output.status.success() {
+                    warn!("'{}' failed, please switch godot-cpp to the '{}' branch yourself.", command, target.godot_cpp_branch());
+                };
+                _ = print_output(output);
+            };
+        };
+    } else {
+        warn!("Could not find the godot-cpp submodule, please switch it to the '{}' branch yourself.", target.godot_cpp_branch());
+    };
+
+    if let Some(mut manifest) = manifest {
+        manifest.project.godot_version = target;
+        manifest.project.godot_cpp_version = target.godot_cpp_branch();
+        manifest.save(ws, path)?;
+    };
+
+    Ok(())
+}
+
+
+/// Returns the generated files of every module. Projects without a manifest are searched for .gdextension files
+fn get_modules(ws: &Workspace, path: &Path, godot_dir: &str, src_dir: &str, manifest: Option<&Manifest>) -> Result<Vec<ModuleFiles>, Error> {
+    let godot_folder = path.join(godot_dir);
+    if let Some(manifest) = manifest {
+        return Ok(manifest.modules.iter().map(|module| ModuleFiles {
+            name: module.name.clone(),
+            entry_symbol: module.entry_symbol.clone(),
+            register_cpp: path.join(&module.src_dir).join("register_types.cpp"),
+            gdextension: godot_folder.join(&module.gdextension),
+        }).collect());
+    };
+
+    let src_folder = path.join(src_dir);
+    let mut modules = vec![];
+    let entries = ws.read_dir(&godot_folder)
+        .with_context(|| format!("Could not find the '{}' folder inside '{}'.", godot_dir, path.display()))?;
+    for gdextension in entries {
+        if gdextension.extension().is_none_or(|ext| ext != "gdextension") {
+            continue;
+        };
+        let name = gdextension.file_stem().unwrap().to_str().unwrap().to_string();
+        let contents = ws.read_to_string(&gdextension).unwrap_or_default();
+        let entry_symbol = get_value(&contents, "entry_symbol").unwrap_or_else(|| format!("{}_library_init", name));
+        // Modules added via 'gdsetup add' live in their own subfolder
+        let register_cpp = if ws.is_dir(&src_folder.join(&name)) {
+            src_folder.join(&name).join("register_types.cpp")
+        } else {
+            src_folder.join("register_types.cpp")
+        };
+        modules.push(ModuleFiles { name, entry_symbol, register_cpp, gdextension });
+    };
+    Ok(modules)
+}
+
+
+/// Guesses the version the project was generated for from its .gdextension files and entry points
+fn detect_version(ws: &Workspace, modules: &[ModuleFiles]) -> GodotVersion {
+    let mut detected = GodotVersion::V4_0;
+    for module in modules {
+        let gdextension = ws.read_to_string(&module.gdextension).unwrap_or_default();
+        if let Some(minimum) = get_value(&gdextension, "compatibility_minimum").and_then(|m| m.parse::<GodotVersion>().ok()) {
+            detected = detected.max(minimum);
+        };
+        let register_cpp = ws.read_to_string(&module.register_cpp).unwrap_or_default();
+        if register_cpp.contains("GDExtensionInterfaceGetProcAddress") {
+            detected = detected.max(GodotVersion::V4_1);
+        };
+    };
+    debug!("Detected Godot {} as the version of the project", detected);
+    detected
+}
+
+
+/// Returns the (unquoted) value of a 'key = "value"' line
+fn get_value(contents: &str, key: &str) -> Option<String> {
+    contents.lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+}
+
+
+/// Rewrites the parameters of the entry point and the arguments of its InitObject. Returns None if the entry point can't be found.
+/// Only the 'module' region is touched if the file has one
+fn upgrade_register_cpp(register_cpp: &str, entry_symbol: &str, version: GodotVersion) -> Option<String> {
+    let (params, args) = codegen::get_entry_point_params(version);
+    let declaration = format!("{}(", entry_symbol);
+    let init_obj = "InitObject init_obj(";
+    let mask = match regions::find_region(register_cpp, "module") {
+        Some(_) => regions::region_mask(register_cpp),
+        None => vec![true; register_cpp.lines().count()],
+    };
+
+    let mut found = false;
+    let mut lines = vec![];
+    for (line, generated) in register_cpp.lines().zip(mask) {
+        if !generated {
+            lines.push(line.to_string());
+            continue;
+        };
+        let start = if line.contains("GDE_EXPORT") && line.contains(&declaration) {
+            found = true;
+            line.find(&declaration).map(|idx| (idx + declaration.len(), params))
+        } else {
+            line.find(init_obj).map(|idx| (idx + init_obj.len(), args))
+        };
+        let replaced = start.and_then(|(start, replacement)| {
+            let end = line.rfind(')').filter(|end| *end >= start)?;
+            Some(format!("{}{}{}", &line[..start], replacement, &line[end..]))
+        });
+        lines.push(replaced.unwrap_or_else(|| line.to_string()));
+    };
+    if !found {
+        return None;
+    };
+
+    Some(regions::join_lines(lines, register_cpp))
+}
+
+
+/// Replaces the version dependent keys of the [configuration] section. A 'reloadable' set by the user is kept.
+fn upgrade_gdextension(gdextension: &str, version: GodotVersion) -> String {
+    let is_versioned = |line: &str| {
+        let key = line.split('=').next().unwrap_or_default().trim();
+        key == "compatibility_minimum" || key == "reloadable"
+    };
+    let reloadable = gdextension.lines().find(|line| line.split('=').next().unwrap_or_default().trim() == "reloadable");
+
+    let mut lines = vec![];
+    for line in gdextension.lines() {
+        if is_versioned(line) {
+            continue;
+        };
+        lines.push(line.to_string());
+        if line.split('=').next().unwrap_or_default().trim() == "entry_symbol" {
+            for configuration in codegen::get_gdextension_configuration(version).lines() {
+                match reloadable {
+                    Some(reloadable) if configuration.starts_with("reloadable") => lines.push(reloadable.to_string()),
+                    _ => lines.push(configuration.to_string()),
+                };
+            };
+        };
+    };
+
+    regions::join_lines(lines, gdextension)
+}
+
+
+/// Adds the class reference to the library for Godot 4.3+. It goes into the 'doc_data' region, older projects get it
+/// after their sources
+fn upgrade_sconstruct(sconstruct: &str, src_dir: &str, version: GodotVersion) -> String {
+    if !version.has_doc_data() || sconstruct.contains("GodotCPPDocData") {
+        return sconstruct.to_string();
+    };
+    let doc_data = codegen::get_sconstruct_doc_data(src_dir).trim().lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    if let Ok(upgraded) = regions::replace_region(sconstruct, "doc_data", &doc_data) {
+        return upgraded;
+    };
+    let Some(sources) = sconstruct.lines().position(|line| line.starts_with("sources = Glob(")) else {
+        warn!("Could not find the sources in SConstruct, please add the class reference yourself.");
+        return sconstruct.to_string();
+    };
+
+    let mut lines = sconstruct.lines().map(|l| l.to_string()).collect::<Vec<String>>();
+    lines.insert(sources + 1, codegen::get_sconstruct_doc_data(src_dir).trim_end().to_string());
+    regions::join_lines(lines, sconstruct)
+}
+
+
+/// Asks a yes/no question on the command line, defaults to no. Without a terminal nobody can answer it
+fn confirm(question: &str) -> Result<bool, Error> {
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::msg(format!("Can't ask '{}', the input is not a terminal. Pass --yes to apply the changes without asking.", question)));
+    };
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).with_context(|| "Tried reading the answer")?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::ExtensionApi, level::InitLevel, templates::Templates};
+
+    fn register_cpp(version: GodotVersion) -> String {
+        let api = ExtensionApi::bundled();
+        codegen::generate_register_cpp(&Templates::builtin(), "example", "example", "Node", InitLevel::Scene, &api, version).unwrap()
+    }
+
+    fn gdextension(version: GodotVersion) -> String {
+        codegen::generate_gdextension(&Templates::builtin(), "example", version).unwrap()
+    }
+
+    #[test]
+    fn upgrades_the_entry_point() {
+        let upgraded = upgrade_register_cpp(&register_cpp(GodotVersion::V4_0), "example_library_init", GodotVersion::V4_1).unwrap();
+        assert_eq!(upgraded, register_cpp(GodotVersion::V4_1));
+        assert!(upgrade_register_cpp(&register_cpp(GodotVersion::V4_0), "other_library_init", GodotVersion::V4_1).is_none());
+
+        // Only the 'module' region is generated, the lines around it belong to the user
+        let user_code = "// InitObject init_obj(p_interface, p_library, r_initialization);\n";
+        let upgraded = upgrade_register_cpp(&format!("{}{}", user_code, register_cpp(GodotVersion::V4_0)), "example_library_init", GodotVersion::V4_1).unwrap();
+        assert_eq!(upgraded, format!("{}{}", user_code, register_cpp(GodotVersion::V4_1)));
+
+        // Projects generated before the regions existed are rewritten completely
+        let legacy = "\
+GDExtensionBool GDE_EXPORT example_library_init(const GDExtensionInterface *p_interface, GDExtensionClassLibraryPtr p_library, GDExtensionInitialization *r_initialization) {
+    godot::GDExtensionBinding::InitObject init_obj(p_interface, p_library, r_initialization);
+";
+        let upgraded = upgrade_register_cpp(legacy, "example_library_init", GodotVersion::V4_1).unwrap();
+        assert!(upgraded.contains("example_library_init(GDExtensionInterfaceGetProcAddress p_get_proc_address, const GDExtensionClassLibraryPtr p_library, GDExtensionInitialization *r_initialization) {"));
+        assert!(upgraded.contains("init_obj(p_get_proc_address, p_library, r_initialization);\n"));
+    }
+
+    #[test]
+    fn upgrades_the_gdextension_configuration() {
+        assert_eq!(upgrade_gdextension(&gdextension(GodotVersion::V4_0), GodotVersion::V4_1), gdextension(GodotVersion::V4_1));
+        assert_eq!(upgrade_gdextension(&gdextension(GodotVersion::V4_1), GodotVersion::V4_3), gdextension(GodotVersion::V4_3));
+
+        // The user turned hot reloading off
+        let configured = gdextension(GodotVersion::V4_2).replace("reloadable = true", "reloadable = false");
+        let upgraded = upgrade_gdextension(&configured, GodotVersion::V4_3);
+        assert!(upgraded.contains("compatibility_minimum = \"4.3\"\nreloadable = false\n"));
+        assert_eq!(upgraded.matches("compatibility_minimum").count(), 1);
+    }
+
+    #[test]
+    fn upgrades_sconstruct_with_the_class_reference() {
+        let templates = Templates::builtin();
+        let sconstruct = |version| codegen::generate_sconstruct(&templates, "example", "godot", "src", version).unwrap();
+        assert_eq!(upgrade_sconstruct(&sconstruct(GodotVersion::V4_2), "src", GodotVersion::V4_3), sconstruct(GodotVersion::V4_3));
+        assert_eq!(upgrade_sconstruct(&sconstruct(GodotVersion::V4_3), "src", GodotVersion::V4_3), sconstruct(GodotVersion::V4_3));
+        assert_eq!(upgrade_sconstruct(&sconstruct(GodotVersion::V4_1), "src", GodotVersion::V4_2), sconstruct(GodotVersion::V4_1));
+
+        // Without the region it goes after the sources
+        let legacy = "sources = Glob(\"src/*.cpp\")\nlibrary = env.SharedLibrary(source=sources)\n";
+        let upgraded = upgrade_sconstruct(legacy, "src", GodotVersion::V4_3);
+        assert!(upgraded.starts_with("sources = Glob(\"src/*.cpp\")\n\nif env[\"target\"] in [\"editor\", \"template_debug\"]:\n"));
+        assert!(upgraded.ends_with("    sources.append(doc_data)\nlibrary = env.SharedLibrary(source=sources)\n"));
+    }
+
+    #[test]
+    fn detects_the_version_of_the_files() {
+        let path = std::env::temp_dir().join(format!("gdsetup-test-detect-{}", std::process::id()));
+        let module = ModuleFiles {
+            name: "example".to_string(),
+            entry_symbol: "example_library_init".to_string(),
+            register_cpp: path.join("src/register_types.cpp"),
+            gdextension: path.join("godot/example.gdextension"),
+        };
+        let mut ws = Workspace::new(true);
+        ws.write(&module.register_cpp, register_cpp(GodotVersion::V4_0)).unwrap();
+        ws.write(&module.gdextension, gdextension(GodotVersion::V4_0)).unwrap();
+        assert_eq!(detect_version(&ws, std::slice::from_ref(&module)), GodotVersion::V4_0);
+
+        // The 4.1 entry point without a compatibility_minimum
+        ws.write(&module.register_cpp, register_cpp(GodotVersion::V4_1)).unwrap();
+        assert_eq!(detect_version(&ws, std::slice::from_ref(&module)), GodotVersion::V4_1);
+        ws.write(&module.gdextension, gdextension(GodotVersion::V4_2)).unwrap();
+        assert_eq!(detect_version(&ws, std::slice::from_ref(&module)), GodotVersion::V4_2);
+    }
+}
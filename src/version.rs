@@ -0,0 +1,109 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+
+/// The Godot versions gdsetup has templates for
+pub const SUPPORTED_VERSIONS: [&str; 5] = ["4.0", "4.1", "4.2", "4.3", "4.4"];
+pub const DEFAULT_VERSION: &str = "4.3";
+
+
+/// A Godot version (only major and minor are relevant for GDExtension)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct GodotVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+
+impl GodotVersion {
+    /// Projects created before gdsetup recorded the version used the Godot 4.0 templates
+    pub const V4_0: GodotVersion = GodotVersion { major: 4, minor: 0 };
+    pub const V4_1: GodotVersion = GodotVersion { major: 4, minor: 1 };
+    pub const V4_2: GodotVersion = GodotVersion { major: 4, minor: 2 };
+    pub const V4_3: GodotVersion = GodotVersion { major: 4, minor: 3 };
+
+    /// Branch of the godot-cpp repository that matches this version
+    pub fn godot_cpp_branch(&self) -> String {
+        format!("{}.{}", self.major, self.minor)
+    }
+
+    /// Value of 'compatibility_minimum' in the .gdextension file. Godot 4.0 does not know that key.
+    pub fn compatibility_minimum(&self) -> Option<String> {
+        if *self >= Self::V4_1 {
+            Some(self.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Since 4.1 the entry point receives 'GDExtensionInterfaceGetProcAddress' instead of the whole interface
+    pub fn uses_get_proc_address(&self) -> bool {
+        *self >= Self::V4_1
+    }
+
+    /// Since 4.1 'VARIANT_ENUM_CAST' and 'VARIANT_BITFIELD_CAST' take the qualified enum ('Class::Enum'), 4.0 needs 'Class, Enum'
+    pub fn has_qualified_enum_casts(&self) -> bool {
+        *self >= Self::V4_1
+    }
+
+    /// Since 4.1 extensions can add editor plugins with 'EditorPlugins::add_by_type'
+    pub fn has_editor_plugins(&self) -> bool {
+        *self >= Self::V4_1
+    }
+
+    /// Since 4.2 extensions can be reloaded while the editor is running
+    pub fn is_reloadable(&self) -> bool {
+        *self >= Self::V4_2
+    }
+
+    /// Since 4.3 godot-cpp can compile the class reference (doc_classes/*.xml) into the library
+    pub fn has_doc_data(&self) -> bool {
+        *self >= Self::V4_3
+    }
+}
+
+
+impl Default for GodotVersion {
+    fn default() -> Self {
+        DEFAULT_VERSION.parse().unwrap()
+    }
+}
+
+impl FromStr for GodotVersion {
+    type Err = Error;
+
+    /// Parses versions like '4.1' or '4.2.1'
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().trim_start_matches('v').split('.');
+        let major = parts.next().and_then(|p| p.parse::<u32>().ok());
+        let minor = parts.next().and_then(|p| p.parse::<u32>().ok());
+        match (major, minor) {
+            (Some(major), Some(minor)) if major == 4 => Ok(GodotVersion { major, minor }),
+            (Some(_), Some(_)) => Err(Error::msg(format!("GDExtension needs Godot 4, '{}' is not supported.", s))),
+            _ => Err(Error::msg(format!("'{}' is not a valid Godot version (e.g. '4.1').", s))),
+        }
+    }
+}
+
+impl TryFrom<String> for GodotVersion {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<GodotVersion> for String {
+    fn from(value: GodotVersion) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for GodotVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
@@ -0,0 +1,401 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}, process::{Command, Output}};
+
+use anyhow::{Result, Error, Context};
+use log::{info, warn};
+use similar::TextDiff;
+
+use crate::get_basecommand;
+
+
+/// A single change a command makes to the disk (or an external command it runs)
+#[derive(Debug, Clone)]
+pub enum Operation {
+    CreateDir(PathBuf),
+    CreateFile { path: PathBuf, contents: Vec<u8> },
+    ModifyFile { path: PathBuf, old: Vec<u8>, new: Vec<u8> },
+    /// 'old' holds the previous contents of 'to' if it has been overwritten
+    Copy { from: PathBuf, to: PathBuf, old: Option<Vec<u8>> },
+    Move { from: PathBuf, to: PathBuf },
+    RemoveFile { path: PathBuf, contents: Vec<u8> },
+    RemoveDir(PathBuf),
+    Command { cwd: PathBuf, command: String },
+}
+
+/// State of a path after the operations of a dry-run
+#[derive(Debug, Clone)]
+enum Entry {
+    File(Vec<u8>),
+    Dir,
+    Removed,
+    /// Something that has been moved here, but still is at the given path on the disk
+    Moved(PathBuf),
+}
+
+
+/// All commands access the disk through a Workspace, so that every change can be recorded.
+/// In dry-run mode nothing is written, the changes are only simulated and can be printed as a plan.
+/// Otherwise the recorded operations serve as a journal to roll back a failed command or to undo the last one.
+#[derive(Debug, Default)]
+pub struct Workspace {
+    dry_run: bool,
+    operations: Vec<Operation>,
+    overlay: BTreeMap<PathBuf, Entry>,
+    root: Option<PathBuf>,
+}
+
+
+impl Workspace {
+    pub fn new(dry_run: bool) -> Self {
+        Workspace {
+            dry_run,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// The project folder the command works in, the journal is saved there
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    pub fn set_root(&mut self, root: &Path) {
+        self.root = Some(root.to_path_buf());
+    }
+
+
+    /// Returns where 'path' currently is on the disk, taking the simulated operations of a dry-run into account.
+    /// Returns None if it only exists (or has been removed) in the simulation.
+    fn real_path(&self, path: &Path) -> Option<PathBuf> {
+        for ancestor in path.ancestors() {
+            match self.overlay.get(ancestor) {
+                None => continue,
+                Some(Entry::Moved(real)) => {
+                    let rest = path.strip_prefix(ancestor).ok()?;
+                    return Some(if rest.as_os_str().is_empty() { real.clone() } else { real.join(rest) });
+                },
+                Some(_) => return None,
+            };
+        };
+        Some(path.to_path_buf())
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        match self.overlay.get(path) {
+            Some(Entry::Removed) => false,
+            Some(Entry::File(_)) | Some(Entry::Dir) => true,
+            // Does not follow symlinks, so that broken ones are found as well
+            _ => self.real_path(path).is_some_and(|real| std::fs::symlink_metadata(real).is_ok()),
+        }
+    }
+
+    pub fn is_dir(&self, path: &Path) -> bool {
+        match self.overlay.get(path) {
+            Some(Entry::Dir) => true,
+            Some(Entry::File(_)) | Some(Entry::Removed) => false,
+            _ => self.real_path(path).is_some_and(|real| real.is_dir()),
+        }
+    }
+
+    pub fn is_symlink(&self, path: &Path) -> bool {
+        match self.overlay.get(path) {
+            Some(Entry::File(_)) | Some(Entry::Dir) | Some(Entry::Removed) => false,
+            _ => self.real_path(path).is_some_and(|real| real.is_symlink()),
+        }
+    }
+
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        match self.overlay.get(path) {
+            Some(Entry::File(contents)) => Ok(contents.clone()),
+            Some(Entry::Dir) | Some(Entry::Removed) => Err(Error::msg(format!("'{}' is not a file", path.display()))),
+            _ => match self.real_path(path) {
+                Some(real) => std::fs::read(&real).with_context(|| format!("Tried reading '{}'", path.display())),
+                None => Err(Error::msg(format!("'{}' does not exist", path.display()))),
+            },
+        }
+    }
+
+    pub fn read_to_string(&self, path: &Path) -> Result<String, Error> {
+        let contents = self.read(path)?;
+        String::from_utf8(contents).with_context(|| format!("'{}' is not valid UTF-8", path.display()))
+    }
+
+    /// Returns the paths of all entries inside a folder
+    pub fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>, Error> {
+        let read = match self.real_path(path) {
+            Some(real) => std::fs::read_dir(real),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        };
+        let mut entries = match read {
+            Ok(read) => read.flatten().map(|entry| path.join(entry.file_name())).collect::<Vec<PathBuf>>(),
+            Err(_) if self.is_dir(path) => vec![],
+            Err(err) => return Err(Error::new(err).context(format!("Tried reading {}", path.display()))),
+        };
+        entries.retain(|entry| !matches!(self.overlay.get(entry), Some(Entry::Removed)));
+        for (entry, state) in self.overlay.iter() {
+            if entry.parent() == Some(path) && !matches!(state, Entry::Removed) && !entries.contains(entry) {
+                entries.push(entry.clone());
+            };
+        };
+        Ok(entries)
+    }
+
+
+    pub fn create_dir(&mut self, path: &Path) -> Result<(), Error> {
+        if self.exists(path) {
+            return Err(Error::msg(format!("'{}' already exists", path.display())));
+        };
+        if !self.dry_run {
+            std::fs::create_dir(path).with_context(|| format!("Failed to create directory '{}'", path.display()))?;
+        } else {
+            self.overlay.insert(path.to_path_buf(), Entry::Dir);
+        };
+        self.operations.push(Operation::CreateDir(path.to_path_buf()));
+        Ok(())
+    }
+
+    pub fn create_dir_all(&mut self, path: &Path) -> Result<(), Error> {
+        if self.is_dir(path) {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                self.create_dir_all(parent)?;
+            };
+        };
+        self.create_dir(path)
+    }
+
+    /// Creates or overwrites a file
+    pub fn write(&mut self, path: &Path, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+        let contents = contents.as_ref().to_vec();
+        let operation = if self.exists(path) {
+            let old = self.read(path)?;
+            if old == contents {
+                return Ok(());
+            };
+            Operation::ModifyFile { path: path.to_path_buf(), old, new: contents.clone() }
+        } else {
+            Operation::CreateFile { path: path.to_path_buf(), contents: contents.clone() }
+        };
+        if !self.dry_run {
+            std::fs::write(path, &contents).with_context(|| format!("Tried writing '{}'", path.display()))?;
+        } else {
+            self.overlay.insert(path.to_path_buf(), Entry::File(contents));
+        };
+        self.operations.push(operation);
+        Ok(())
+    }
+
+    pub fn copy(&mut self, from: &Path, to: &Path) -> Result<(), Error> {
+        let old = if self.exists(to) { Some(self.read(to)?) } else { None };
+        if !self.dry_run {
+            std::fs::copy(from, to).with_context(|| format!("Tried copying {} into {}", from.display(), to.display()))?;
+        } else {
+            let contents = self.read(from)?;
+            self.overlay.insert(to.to_path_buf(), Entry::File(contents));
+        };
+        self.operations.push(Operation::Copy { from: from.to_path_buf(), to: to.to_path_buf(), old });
+        Ok(())
+    }
+
+    /// Moves a file or folder
+    pub fn rename(&mut self, from: &Path, to: &Path) -> Result<(), Error> {
+        if !self.exists(from) {
+            return Err(Error::msg(format!("'{}' does not exist", from.display())));
+        };
+        if !self.dry_run {
+            std::fs::rename(from, to).with_context(|| format!("Tried renaming {} to {}", from.display(), to.display()))?;
+        } else {
+            let entry = match (self.overlay.get(from), self.real_path(from)) {
+                (Some(Entry::File(contents)), _) => Entry::File(contents.clone()),
+                (Some(Entry::Dir), _) => Entry::Dir,
+                (_, Some(real)) => Entry::Moved(real),
+                _ => return Err(Error::msg(format!("'{}' does not exist", from.display()))),
+            };
+            // Simulated entries inside of a folder move along with it
+            let children = self.overlay.keys().filter(|path| path.starts_with(from) && *path != from).cloned().collect::<Vec<PathBuf>>();
+            for child in children {
+                if let (Some(state), Ok(rest)) = (self.overlay.remove(&child), child.strip_prefix(from)) {
+                    self.overlay.insert(to.join(rest), state);
+                };
+            };
+            self.overlay.insert(to.to_path_buf(), entry);
+            self.overlay.insert(from.to_path_buf(), Entry::Removed);
+        };
+        self.operations.push(Operation::Move { from: from.to_path_buf(), to: to.to_path_buf() });
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, path: &Path) -> Result<(), Error> {
+        if !self.exists(path) {
+            return Err(Error::msg(format!("Failed to remove '{}', it does not exist", path.display())));
+        };
+        // Keep the contents around so that the removal can be reverted
+        let contents = self.read(path)?;
+        if !self.dry_run {
+            std::fs::remove_file(path).with_context(|| format!("Failed to remove '{}'", path.display()))?;
+        } else {
+            self.overlay.insert(path.to_path_buf(), Entry::Removed);
+        };
+        self.operations.push(Operation::RemoveFile { path: path.to_path_buf(), contents });
+        Ok(())
+    }
+
+    /// Removes an empty folder
+    pub fn remove_dir(&mut self, path: &Path) -> Result<(), Error> {
+        if !self.read_dir(path)?.is_empty() {
+            return Err(Error::msg(format!("Failed to remove '{}', it is not empty", path.display())));
+        };
+        if !self.dry_run {
+            std::fs::remove_dir(path).with_context(|| format!("Failed to remove '{}'", path.display()))?;
+        } else {
+            self.overlay.insert(path.to_path_buf(), Entry::Removed);
+        };
+        self.operations.push(Operation::RemoveDir(path.to_path_buf()));
+        Ok(())
+    }
+
+    /// Runs a shell command inside 'cwd'. Returns None in dry-run mode.
+    pub fn run(&mut self, cwd: &Path, command: &str) -> Result<Option<Output>, Error> {
+        self.operations.push(Operation::Command { cwd: cwd.to_path_buf(), command: command.to_string() });
+        if self.dry_run {
+            return Ok(None);
+        };
+        info!("Running '{}'", command);
+        let basecmd = get_basecommand();
+        let output = Command::new(basecmd.0).arg(basecmd.1)
+            .arg(command)
+            .current_dir(cwd)
+            .output().with_context(|| format!("Tried to run '{}'", command))?;
+        Ok(Some(output))
+    }
+
+
+    /// Reverts the given operations (in reverse order). External commands can't be reverted and are skipped with a warning.
+    pub fn revert(&mut self, operations: &[Operation]) -> Result<(), Error> {
+        for operation in operations.iter().rev() {
+            match operation {
+                Operation::CreateDir(path) => {
+                    if self.exists(path) {
+                        if let Err(err) = self.remove_dir(path) {
+                            warn!("Could not revert the creation of '{}': {}", path.display(), err);
+                        };
+                    };
+                },
+                Operation::CreateFile { path, .. } => {
+                    if self.exists(path) {
+                        self.remove_file(path)?;
+                    };
+                },
+                Operation::ModifyFile { path, old, .. } => self.write(path, old)?,
+                Operation::Copy { to, old, .. } => match old {
+                    Some(old) => self.write(to, old)?,
+                    None => self.remove_file(to)?,
+                },
+                Operation::Move { from, to } => self.rename(to, from)?,
+                Operation::RemoveFile { path, contents } => self.write(path, contents)?,
+                Operation::RemoveDir(path) => self.create_dir(path)?,
+                Operation::Command { command, .. } => warn!("'{}' can not be reverted automatically", command),
+            };
+        };
+        Ok(())
+    }
+
+    /// Reverts everything this workspace has done so far
+    pub fn rollback(&mut self) -> Result<(), Error> {
+        if self.dry_run || self.operations.is_empty() {
+            return Ok(());
+        };
+        warn!("Rolling back {} operation(s)", self.operations.len());
+        let operations = std::mem::take(&mut self.operations);
+        self.revert(&operations)?;
+        // The rollback itself is not part of the journal
+        self.operations.clear();
+        Ok(())
+    }
+
+
+    /// Prints every recorded operation, file modifications are shown as unified diffs
+    pub fn print_plan(&self) {
+        if self.operations.is_empty() {
+            println!("Nothing to do.");
+            return;
+        };
+        for operation in self.operations.iter() {
+            match operation {
+                Operation::CreateDir(path) => println!("create directory {}", path.display()),
+                Operation::CreateFile { path, contents } => {
+                    println!("create file      {} ({} lines)", path.display(), String::from_utf8_lossy(contents).lines().count());
+                },
+                Operation::ModifyFile { path, old, new } => {
+                    println!("modify file      {}", path.display());
+                    let old = String::from_utf8_lossy(old);
+                    let new = String::from_utf8_lossy(new);
+                    let name = path.display().to_string();
+                    let diff = TextDiff::from_lines(old.as_ref(), new.as_ref());
+                    print!("{}", diff.unified_diff().context_radius(3).header(&name, &name));
+                },
+                Operation::Copy { from, to, .. } => println!("copy             {} -> {}", from.display(), to.display()),
+                Operation::Move { from, to } => println!("move             {} -> {}", from.display(), to.display()),
+                Operation::RemoveFile { path, .. } => println!("delete file      {}", path.display()),
+                Operation::RemoveDir(path) => println!("delete directory {}", path.display()),
+                Operation::Command { cwd, command } => println!("run              '{}' in {}", command, cwd.display()),
+            };
+        };
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollback_reverts_partial_writes() {
+        let dir = std::env::temp_dir().join(format!("gdsetup-test-rollback-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("SConstruct"), "old").unwrap();
+        std::fs::write(dir.join("src/a.cpp"), "a").unwrap();
+
+        let mut ws = Workspace::new(false);
+        ws.set_root(&dir);
+        ws.write(&dir.join("SConstruct"), "new").unwrap();
+        ws.create_dir_all(&dir.join("godot/bin")).unwrap();
+        ws.write(&dir.join("godot/bin/b.gdextension"), "b").unwrap();
+        ws.rename(&dir.join("src/a.cpp"), &dir.join("godot/a.cpp")).unwrap();
+        ws.remove_dir(&dir.join("src")).unwrap();
+        // The command fails here, everything it did so far is reverted
+        ws.rollback().unwrap();
+
+        assert!(ws.operations().is_empty());
+        assert_eq!(std::fs::read_to_string(dir.join("SConstruct")).unwrap(), "old");
+        assert_eq!(std::fs::read_to_string(dir.join("src/a.cpp")).unwrap(), "a");
+        assert!(!dir.join("godot").exists());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dry_runs_only_change_the_overlay() {
+        let dir = std::env::temp_dir().join(format!("gdsetup-test-dry-run-{}", std::process::id()));
+        let mut ws = Workspace::new(true);
+        ws.create_dir_all(&dir.join("src")).unwrap();
+        ws.write(&dir.join("src/a.cpp"), "a").unwrap();
+        ws.rename(&dir.join("src"), &dir.join("source")).unwrap();
+
+        assert!(!dir.exists());
+        assert_eq!(ws.read_to_string(&dir.join("source/a.cpp")).unwrap(), "a");
+        assert!(!ws.exists(&dir.join("src")));
+        assert_eq!(ws.read_dir(&dir).unwrap(), vec![dir.join("source")]);
+        // Nothing has been written, so there is nothing to roll back
+        ws.rollback().unwrap();
+        assert_eq!(ws.operations().len(), 4);
+    }
+}
@@ -0,0 +1,43 @@
+#include "{{ class_file }}.h"
+
+#include <godot_cpp/core/class_db.hpp>
+
+#include <godot_cpp/classes/global_constants.hpp>
+#include <godot_cpp/variant/utility_functions.hpp>
+
+using namespace godot;
+{{ class_name }}::{{ class_name }}() {
+}
+{{ class_name }}::~{{ class_name }}() {
+}
+void {{ class_name }}::exampleFunction(int number) {
+    UtilityFunctions::print("Your number was: ", number);
+}
+{% if overrides %}
+{% for method in overrides %}
+
+{{ method.return_type }} {{ class_name }}::{{ method.name }}({{ method.params }}){% if method.is_const %} const{% endif %} {
+{% if method.return_type != "void" %}
+    return {};
+{% endif %}
+}
+{% endfor %}
+{% elif is_node %}
+
+void {{ class_name }}::_ready() {
+}
+
+void {{ class_name }}::_process(double delta) {
+}
+
+void {{ class_name }}::_physics_process(double delta) {
+}
+{% elif is_resource %}
+
+void {{ class_name }}::_get_property_list(List<PropertyInfo> *p_list) const {
+}
+{% endif %}
+
+void {{ class_name }}::_bind_methods() {
+    ClassDB::bind_method(D_METHOD("exampleFunction", "number"), &{{ class_name }}::exampleFunction);
+}
@@ -0,0 +1,21 @@
+// Generated by gdsetup from '{{ header }}'. Edit the '@gd' annotations of the header and run 'gdsetup bind' instead of this file.
+
+#include "{{ header }}"
+
+#include <godot_cpp/core/class_db.hpp>
+
+using namespace godot;
+{% for class in classes %}
+{% for emitter in class.emitters %}
+
+{{ emitter.signature }} {
+    emit_signal({{ emitter.args }});
+}
+{% endfor %}
+
+void {{ class.name }}::_bind_methods() {
+{% for binding in class.bindings %}
+    {{ binding }}
+{% endfor %}
+}
+{% endfor %}
@@ -0,0 +1,54 @@
+#ifndef {{ class_file_upper }}_CLASS_H
+#define {{ class_file_upper }}_CLASS_H
+
+// We don't need windows.h in this example plugin but many others do, and it can
+// lead to annoying situations due to the ton of macros it defines.
+// So we include it and make sure CI warns us if we use something that conflicts
+// with a Windows define.
+#ifdef WIN32
+#include <windows.h>
+#endif
+
+#include <godot_cpp/classes/global_constants.hpp>
+#include <godot_cpp/variant/utility_functions.hpp>
+#include <{{ base_include }}>
+{% for include in override_includes %}
+#include <{{ include }}>
+{% endfor %}
+{% if is_resource %}
+#include <godot_cpp/templates/list.hpp>
+{% endif %}
+
+#include <godot_cpp/core/binder_common.hpp>
+
+using namespace godot;
+
+class {{ class_name }} : public {{ base_class }} {
+    GDCLASS({{ class_name }}, {{ base_class }});
+
+private:
+
+protected:
+    static void _bind_methods();
+
+public:
+    {{ class_name }}();
+    ~{{ class_name }}();
+{% if overrides %}
+
+{% for method in overrides %}
+    {{ method.return_type }} {{ method.name }}({{ method.params }}){% if method.is_const %} const{% endif %} override;
+{% endfor %}
+{% elif is_node %}
+
+    void _ready() override;
+    void _process(double delta) override;
+    void _physics_process(double delta) override;
+{% elif is_resource %}
+
+    void _get_property_list(List<PropertyInfo> *p_list) const;
+{% endif %}
+
+    void exampleFunction(int number);
+};
+#endif // {{ class_file_upper }}_CLASS_H
@@ -0,0 +1,53 @@
+#include "register_types.h"
+
+#include <gdextension_interface.h>
+
+#include <godot_cpp/core/class_db.hpp>
+#include <godot_cpp/core/defs.hpp>
+#include <godot_cpp/godot.hpp>
+
+// gdsetup:begin includes
+{% if is_editor_plugin %}
+#include <godot_cpp/classes/editor_plugin_registration.hpp>
+{% endif %}
+#include "{{ class_file }}.h"
+// gdsetup:end
+//#include "tests.h"
+
+using namespace godot;
+
+// gdsetup:begin module
+// Note: It is not recommended to rename that function, except you know what you are doing
+void initialize_{{ module_name }}_module(ModuleInitializationLevel p_level) {
+    // gdsetup:begin registrations
+{% for registration in registrations %}
+{% if registration %}
+    {{ registration }}
+{% else %}
+
+{% endif %}
+{% endfor %}
+    // gdsetup:end
+}
+
+// Note: It is not recommended to rename that function, except you know what you are doing
+void uninitialize_{{ module_name }}_module(ModuleInitializationLevel p_level) {
+    if (p_level != {{ minimum_level }}) {
+        return;
+    }
+}
+
+extern "C" {
+// Initialization.
+// Note: It is not recommended to rename that function, except you know what you are doing
+GDExtensionBool GDE_EXPORT {{ entry_symbol }}({{ entry_point_params }}) {
+    godot::GDExtensionBinding::InitObject init_obj({{ entry_point_args }});
+
+    init_obj.register_initializer(initialize_{{ module_name }}_module);
+    init_obj.register_terminator(uninitialize_{{ module_name }}_module);
+    init_obj.set_minimum_library_initialization_level({{ minimum_level }});
+
+    return init_obj.init();
+}
+}
+// gdsetup:end
@@ -0,0 +1,15 @@
+// gdsetup:begin module
+#ifndef {{ module_name_upper }}_REGISTER_TYPES_H
+#define {{ module_name_upper }}_REGISTER_TYPES_H
+
+#include <godot_cpp/core/class_db.hpp>
+
+using namespace godot;
+
+// Note: It is not recommended to rename that function, except you know what you are doing
+void initialize_{{ module_name }}_module(ModuleInitializationLevel p_level);
+// Note: It is not recommended to rename that function, except you know what you are doing
+void uninitialize_{{ module_name }}_module(ModuleInitializationLevel p_level);
+
+#endif // {{ module_name_upper }}_REGISTER_TYPES_H
+// gdsetup:end